@@ -0,0 +1,1870 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::location::Position;
+
+use regex::Regex;
+use ropey::Rope;
+
+/// File size above which loading streams into the rope rather than going
+/// through an intermediate `String`.
+const STREAM_THRESHOLD: u64 = 1 << 20;
+
+/// Turns the opaque `InvalidData` that `read_to_string`/`from_reader`
+/// produce for bad bytes into an error that names the file and the real
+/// problem.
+fn map_read_error(err: io::Error, path: &Path) -> io::Error {
+    if err.kind() == io::ErrorKind::InvalidData {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not valid UTF-8", path.display()),
+        )
+    } else {
+        err
+    }
+}
+
+/// How many undo units a buffer remembers by default. Older units fall
+/// off the front so history can't grow without bound.
+pub const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
+/// One primitive buffer change, remembered with enough text to be
+/// inverted by undo and re-applied by redo.
+#[derive(Debug, Clone)]
+enum Edit {
+    Insert { at: usize, text: String },
+    Delete { at: usize, text: String },
+}
+
+/// Identifies a [`Buffer`] within an [`Editor`](crate::Editor). Ids are
+/// handed out by the editor and are never reused within a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferId(usize);
+
+impl BufferId {
+    /// Wraps a raw id. Embedders should prefer
+    /// [`Editor::allocate_buffer_id`](crate::Editor::allocate_buffer_id),
+    /// which guarantees uniqueness within a session.
+    pub fn new(id: usize) -> BufferId {
+        BufferId(id)
+    }
+
+    pub fn as_usize(self) -> usize {
+        self.0
+    }
+}
+
+/// A single piece of text being edited, backed by a rope. A buffer may be
+/// associated with a file on disk, in which case `filepath` is set and
+/// `save` writes back to it.
+pub struct Buffer {
+    id: BufferId,
+    text: Rope,
+    /// Where this buffer's contents came from and where `save` writes to.
+    /// `None` for scratch buffers.
+    pub filepath: Option<PathBuf>,
+    /// Display name for buffers that have no file, like `*stdin*`.
+    pub name: Option<String>,
+    /// `history.len()` at the last save, or `None` when the saved state
+    /// is no longer reachable through undo — its unit was trimmed from
+    /// the history, or a fresh edit discarded the undone branch it lived
+    /// on. [`Buffer::is_modified`] compares this against the current
+    /// history length, so undoing back to the save point makes the
+    /// buffer clean again and redoing past it dirties it.
+    saved_history_len: Option<usize>,
+    /// Edits are rejected while set. Files opened without write
+    /// permission start read-only; special views can set it explicitly.
+    read_only: bool,
+    /// Whether the file started with a UTF-8 byte-order mark. The BOM is
+    /// stripped on load and re-emitted on save so round-tripping a file
+    /// doesn't change it.
+    had_bom: bool,
+    /// Whether the loaded contents used `\r\n` line endings. Detected
+    /// once at load — typed newlines are always `\n` — and only
+    /// reported, never converted.
+    uses_crlf: bool,
+    /// Undo history, newest unit last. Each unit holds the primitive
+    /// edits of one command, so a multi-cursor insert undoes as a whole.
+    /// The history lives on the buffer, not the editor, so switching
+    /// buffers neither loses nor mixes histories.
+    history: Vec<Vec<Edit>>,
+    /// Units undone and waiting to be redone. Cleared by any fresh edit.
+    redo_stack: Vec<Vec<Edit>>,
+    /// Upper bound on `history`; the oldest unit is dropped beyond it.
+    history_limit: usize,
+    /// While a group is open, recorded edits join the current unit
+    /// instead of each becoming their own.
+    group_open: bool,
+    /// Whether the open group has received its first edit yet.
+    group_started: bool,
+}
+
+impl Buffer {
+    /// Creates an empty, unnamed buffer.
+    pub fn new(id: BufferId) -> Buffer {
+        Buffer {
+            id,
+            text: Rope::new(),
+            filepath: None,
+            name: None,
+            saved_history_len: Some(0),
+            read_only: false,
+            had_bom: false,
+            uses_crlf: false,
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            group_open: false,
+            group_started: false,
+        }
+    }
+
+    /// Creates an unnamed buffer holding `contents`. The buffer has no
+    /// backing file and starts unmodified.
+    pub fn from_str(id: BufferId, contents: &str) -> Buffer {
+        Buffer {
+            id,
+            text: Rope::from_str(contents),
+            filepath: None,
+            name: None,
+            saved_history_len: Some(0),
+            read_only: false,
+            had_bom: false,
+            uses_crlf: contents.contains("\r\n"),
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            group_open: false,
+            group_started: false,
+        }
+    }
+
+    /// Reads everything from `reader` into a new buffer, as for piped
+    /// stdin.
+    pub fn from_reader<R: io::Read>(id: BufferId, mut reader: R) -> io::Result<Buffer> {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+
+        Ok(Buffer::from_str(id, &contents))
+    }
+
+    /// Loads a buffer from the file at `path`. Files we cannot write to
+    /// open read-only. Files above [`STREAM_THRESHOLD`] stream straight
+    /// into the rope instead of being read into one `String` first.
+    pub fn from_file(id: BufferId, path: &Path) -> io::Result<Buffer> {
+        let meta = fs::metadata(path)?;
+        let read_only = meta.permissions().readonly();
+
+        let (mut text, mut had_bom) = if meta.len() > STREAM_THRESHOLD {
+            let reader = io::BufReader::new(fs::File::open(path)?);
+            let text = Rope::from_reader(reader).map_err(|err| map_read_error(err, path))?;
+            (text, false)
+        } else {
+            let contents = fs::read_to_string(path).map_err(|err| map_read_error(err, path))?;
+            (Rope::from_str(&contents), false)
+        };
+
+        // Strip a leading UTF-8 BOM so it doesn't show up as a stray
+        // \u{FEFF} char in the rope, but remember it was there.
+        if text.len_chars() > 0 && text.char(0) == '\u{FEFF}' {
+            text.remove(0..1);
+            had_bom = true;
+        }
+
+        let uses_crlf = contains_crlf(&text);
+
+        Ok(Buffer {
+            id,
+            text,
+            filepath: Some(path.to_path_buf()),
+            name: None,
+            saved_history_len: Some(0),
+            read_only,
+            had_bom,
+            uses_crlf,
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            group_open: false,
+            group_started: false,
+        })
+    }
+
+    pub fn id(&self) -> BufferId {
+        self.id
+    }
+
+    /// Inserts `text` at the given char offset. A no-op on read-only
+    /// buffers.
+    pub fn insert(&mut self, offset: usize, text: &str) {
+        if self.read_only {
+            return;
+        }
+
+        self.text.insert(offset, text);
+        self.record(Edit::Insert {
+            at: offset,
+            text: text.to_string(),
+        });
+    }
+
+    /// Deletes the chars in `start..end`. A no-op on read-only buffers.
+    pub fn delete(&mut self, start: usize, end: usize) {
+        if self.read_only {
+            return;
+        }
+
+        let deleted = self.slice(start, end);
+        self.text.remove(start..end);
+        self.record(Edit::Delete {
+            at: start,
+            text: deleted,
+        });
+    }
+
+    /// Remembers `edit` for undo. A fresh edit invalidates anything that
+    /// was undone, as in every linear-history editor.
+    fn record(&mut self, edit: Edit) {
+        // If the save point sits on the undone branch being discarded,
+        // no amount of undoing can reach it any more.
+        if matches!(self.saved_history_len, Some(saved) if saved > self.history.len()) {
+            self.saved_history_len = None;
+        }
+        self.redo_stack.clear();
+
+        if self.group_open && self.group_started {
+            self.history.last_mut().expect("open group exists").push(edit);
+            return;
+        }
+
+        self.group_started = self.group_open;
+        self.history.push(vec![edit]);
+
+        if self.history.len() > self.history_limit {
+            self.history.remove(0);
+            self.drop_oldest_unit_from_save_point();
+        }
+    }
+
+    /// Adjusts the save point after `history.remove(0)`: indices shift
+    /// down by one, and a save point sitting on the dropped unit is gone
+    /// for good.
+    fn drop_oldest_unit_from_save_point(&mut self) {
+        self.saved_history_len = match self.saved_history_len {
+            Some(0) | None => None,
+            Some(saved) => Some(saved - 1),
+        };
+    }
+
+    /// Starts an undo unit: every edit until
+    /// [`Buffer::end_edit_group`] joins it, so a command that makes
+    /// several primitive edits undoes in one step.
+    pub fn begin_edit_group(&mut self) {
+        self.group_open = true;
+        self.group_started = false;
+    }
+
+    /// Closes the unit opened by [`Buffer::begin_edit_group`]. A group
+    /// that saw no edits leaves no trace.
+    pub fn end_edit_group(&mut self) {
+        self.group_open = false;
+        self.group_started = false;
+    }
+
+    /// Reverts the most recent undo unit, returning the char offset the
+    /// cursor should land on, or `None` with nothing to undo.
+    pub fn undo(&mut self) -> Option<usize> {
+        let unit = self.history.pop()?;
+        let mut cursor = 0;
+
+        // Inverting in reverse order keeps every recorded offset valid.
+        for edit in unit.iter().rev() {
+            cursor = match edit {
+                Edit::Insert { at, text } => {
+                    self.text.remove(*at..at + text.chars().count());
+                    *at
+                }
+                Edit::Delete { at, text } => {
+                    self.text.insert(*at, text);
+                    at + text.chars().count()
+                }
+            };
+        }
+
+        self.redo_stack.push(unit);
+
+        Some(cursor)
+    }
+
+    /// Re-applies the most recently undone unit, returning the char
+    /// offset the cursor should land on, or `None` with nothing to redo.
+    pub fn redo(&mut self) -> Option<usize> {
+        let unit = self.redo_stack.pop()?;
+        let mut cursor = 0;
+
+        for edit in &unit {
+            cursor = match edit {
+                Edit::Insert { at, text } => {
+                    self.text.insert(*at, text);
+                    at + text.chars().count()
+                }
+                Edit::Delete { at, text } => {
+                    self.text.remove(*at..at + text.chars().count());
+                    *at
+                }
+            };
+        }
+
+        self.history.push(unit);
+
+        Some(cursor)
+    }
+
+    /// Caps how many undo units this buffer keeps.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+
+        while self.history.len() > limit {
+            self.history.remove(0);
+            self.drop_oldest_unit_from_save_point();
+        }
+    }
+
+    /// Swaps the char before `cursor` with the char at `cursor`, or at
+    /// the end of a line or of the buffer the two preceding chars, and
+    /// returns the advanced cursor. A no-op at the start of the buffer or
+    /// when there aren't two chars to swap. Operates on char offsets, so
+    /// multi-byte chars are swapped whole.
+    pub fn transpose_chars(&mut self, cursor: usize) -> usize {
+        if self.read_only || cursor == 0 {
+            return cursor;
+        }
+
+        let at_boundary = cursor >= self.text.len_chars() || self.text.char(cursor) == '\n';
+        let (first, second) = if at_boundary {
+            if cursor < 2 {
+                return cursor;
+            }
+            (cursor - 2, cursor - 1)
+        } else {
+            (cursor - 1, cursor)
+        };
+
+        let swapped = format!("{}{}", self.text.char(second), self.text.char(first));
+        self.begin_edit_group();
+        self.delete(first, second + 1);
+        self.insert(first, &swapped);
+        self.end_edit_group();
+
+        second + 1
+    }
+
+    /// Sorts the lines touched by the char range `range` — the whole
+    /// buffer when `None` — lexicographically, in place, as one undo
+    /// unit. The range is widened to whole lines first, and a final
+    /// line without a trailing newline stays without one. A no-op on
+    /// read-only buffers.
+    pub fn sort_lines(&mut self, range: Option<(usize, usize)>, ignore_case: bool, reverse: bool) {
+        if self.read_only {
+            return;
+        }
+
+        let (start, end) = range.unwrap_or((0, self.text.len_chars()));
+
+        let start_line = self.text.char_to_line(start);
+        let mut end_line = self.text.char_to_line(end.min(self.text.len_chars()));
+
+        // A selection ending exactly at a line start doesn't include
+        // that line.
+        if end_line > start_line && end == self.text.line_to_char(end_line) {
+            end_line -= 1;
+        }
+
+        let start = self.text.line_to_char(start_line);
+        let end = if end_line + 1 < self.text.len_lines() {
+            self.text.line_to_char(end_line + 1)
+        } else {
+            self.text.len_chars()
+        };
+
+        let region = self.slice(start, end);
+        let trailing_newline = region.ends_with('\n');
+        let body = region.strip_suffix('\n').unwrap_or(&region);
+
+        let mut lines: Vec<&str> = body.split('\n').collect();
+
+        if ignore_case {
+            lines.sort_by_key(|line| line.to_lowercase());
+        } else {
+            lines.sort_unstable();
+        }
+        if reverse {
+            lines.reverse();
+        }
+
+        let mut sorted = lines.join("\n");
+        if trailing_newline {
+            sorted.push('\n');
+        }
+
+        if sorted == region {
+            return;
+        }
+
+        self.begin_edit_group();
+        self.delete(start, end);
+        self.insert(start, &sorted);
+        self.end_edit_group();
+    }
+
+    /// Comments or uncomments the lines touched by the char range
+    /// `range` — the whole buffer when `None` — with `token`, e.g.
+    /// `"// "`. When every non-blank line is already commented the
+    /// tokens come off; otherwise every non-blank line gets one, after
+    /// its leading whitespace. One undo unit; a no-op on read-only
+    /// buffers.
+    pub fn toggle_line_comment(&mut self, range: Option<(usize, usize)>, token: &str) {
+        if self.read_only {
+            return;
+        }
+
+        let (start, end) = range.unwrap_or((0, self.text.len_chars()));
+
+        let start_line = self.text.char_to_line(start);
+        let mut end_line = self.text.char_to_line(end.min(self.text.len_chars()));
+
+        // A selection ending exactly at a line start doesn't include
+        // that line.
+        if end_line > start_line && end == self.text.line_to_char(end_line) {
+            end_line -= 1;
+        }
+
+        let start = self.text.line_to_char(start_line);
+        let end = if end_line + 1 < self.text.len_lines() {
+            self.text.line_to_char(end_line + 1)
+        } else {
+            self.text.len_chars()
+        };
+
+        let region = self.slice(start, end);
+        let trailing_newline = region.ends_with('\n');
+        let body = region.strip_suffix('\n').unwrap_or(&region);
+
+        let bare = token.trim_end();
+        let all_commented = body
+            .split('\n')
+            .filter(|line| !line.trim().is_empty())
+            .all(|line| line.trim_start().starts_with(bare));
+
+        // Blank lines are never touched, and an all-blank region has
+        // nothing to toggle.
+        if body.split('\n').all(|line| line.trim().is_empty()) {
+            return;
+        }
+
+        let toggled: Vec<String> = body
+            .split('\n')
+            .map(|line| {
+                if line.trim().is_empty() {
+                    return line.to_string();
+                }
+
+                let indent = line.len() - line.trim_start().len();
+
+                if all_commented {
+                    let rest = &line[indent..];
+                    // Take the full token with its space when present,
+                    // the bare token otherwise.
+                    let stripped = rest
+                        .strip_prefix(token)
+                        .or_else(|| rest.strip_prefix(bare))
+                        .unwrap_or(rest);
+                    format!("{}{}", &line[..indent], stripped)
+                } else {
+                    format!("{}{}{}", &line[..indent], token, &line[indent..])
+                }
+            })
+            .collect();
+
+        let mut toggled = toggled.join("\n");
+        if trailing_newline {
+            toggled.push('\n');
+        }
+
+        if toggled == region {
+            return;
+        }
+
+        self.begin_edit_group();
+        self.delete(start, end);
+        self.insert(start, &toggled);
+        self.end_edit_group();
+    }
+
+    /// Whether `line` is empty or holds only whitespace, which is what
+    /// paragraph motion treats as a boundary.
+    fn line_is_blank(&self, line: usize) -> bool {
+        self.text.line(line).chars().all(char::is_whitespace)
+    }
+
+    /// Char offset of the next paragraph boundary after `cursor` — the
+    /// first blank line past the current paragraph, or the end of the
+    /// buffer from the last one — like Vim's `}`. A run of consecutive
+    /// blank lines is one boundary. `None` when already at the end.
+    pub fn move_paragraph_forward(&self, cursor: usize) -> Option<usize> {
+        let len = self.text.len_chars();
+        if cursor >= len {
+            return None;
+        }
+
+        let mut line = self.text.char_to_line(cursor);
+
+        // Step out of a blank run the cursor is already in, then cross
+        // the paragraph to the blank line after it.
+        while line < self.len_lines() && self.line_is_blank(line) {
+            line += 1;
+        }
+        while line < self.len_lines() && !self.line_is_blank(line) {
+            line += 1;
+        }
+
+        let target = if line < self.len_lines() {
+            self.line_to_char(line)
+        } else {
+            len
+        };
+
+        (target != cursor).then_some(target)
+    }
+
+    /// The counterpart of [`Buffer::move_paragraph_forward`]: the blank
+    /// line above the current paragraph, or the start of the buffer,
+    /// like Vim's `{`. `None` when already at the start.
+    pub fn move_paragraph_backward(&self, cursor: usize) -> Option<usize> {
+        if cursor == 0 {
+            return None;
+        }
+
+        let mut line = self.text.char_to_line(cursor);
+        if line == 0 {
+            return Some(0);
+        }
+        line -= 1;
+
+        while line > 0 && self.line_is_blank(line) {
+            line -= 1;
+        }
+        while line > 0 && !self.line_is_blank(line) {
+            line -= 1;
+        }
+
+        Some(self.line_to_char(line))
+    }
+
+    /// Collapses every run of two or more consecutive blank lines into
+    /// a single blank one and leaves the buffer ending in exactly one
+    /// newline, for tidying prose formats like Markdown. One undo unit;
+    /// a no-op on read-only and already-normalized buffers.
+    pub fn collapse_blank_lines(&mut self) {
+        if self.read_only {
+            return;
+        }
+
+        let original = self.to_string();
+        let mut lines: Vec<&str> = Vec::new();
+        let mut blanks = 0;
+
+        for line in original.split('\n') {
+            if line.is_empty() {
+                blanks += 1;
+                continue;
+            }
+
+            // One blank line survives out of however many piled up;
+            // a trailing run never reaches this and vanishes entirely.
+            if blanks > 0 {
+                lines.push("");
+            }
+            lines.push(line);
+            blanks = 0;
+        }
+
+        let mut normalized = lines.join("\n");
+        if !normalized.is_empty() {
+            normalized.push('\n');
+        }
+
+        if normalized == original {
+            return;
+        }
+
+        self.begin_edit_group();
+        self.delete(0, self.text.len_chars());
+        self.insert(0, &normalized);
+        self.end_edit_group();
+    }
+
+    /// Deletes from `cursor` to the end of its line, or when already at
+    /// the end of a line deletes the newline so the next line joins this
+    /// one. Returns the killed text and the (unmoved) cursor. At the true
+    /// end of the buffer this is a no-op and the killed text is empty.
+    pub fn kill_to_line_end(&mut self, cursor: usize) -> (String, usize) {
+        if self.read_only || cursor >= self.text.len_chars() {
+            return (String::new(), cursor);
+        }
+
+        let line = self.char_to_line(cursor);
+        let line_end = self.line_to_char(line) + self.line_len(line);
+        let end = if cursor == line_end { cursor + 1 } else { line_end };
+
+        let killed = self.slice(cursor, end);
+        self.delete(cursor, end);
+
+        (killed, cursor)
+    }
+
+    /// Deletes the word before `cursor` — intervening whitespace and
+    /// then the run of non-whitespace in front of it, words being what
+    /// [`Buffer::word_count`] counts — and returns the killed text with
+    /// the new cursor. A no-op at the start of the buffer, returning an
+    /// empty string.
+    pub fn delete_word_backward(&mut self, cursor: usize) -> (String, usize) {
+        let cursor = cursor.min(self.text.len_chars());
+
+        if self.read_only || cursor == 0 {
+            return (String::new(), cursor);
+        }
+
+        let mut start = cursor;
+        while start > 0 && self.text.char(start - 1).is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !self.text.char(start - 1).is_whitespace() {
+            start -= 1;
+        }
+
+        let killed = self.slice(start, cursor);
+        self.delete(start, cursor);
+
+        (killed, start)
+    }
+
+    /// The forward counterpart of [`Buffer::delete_word_backward`]:
+    /// deletes through the end of the word at or after `cursor`. The
+    /// cursor stays put. A no-op at the end of the buffer.
+    pub fn delete_word_forward(&mut self, cursor: usize) -> (String, usize) {
+        let len = self.text.len_chars();
+        let cursor = cursor.min(len);
+
+        if self.read_only || cursor == len {
+            return (String::new(), cursor);
+        }
+
+        let mut end = cursor;
+        while end < len && self.text.char(end).is_whitespace() {
+            end += 1;
+        }
+        while end < len && !self.text.char(end).is_whitespace() {
+            end += 1;
+        }
+
+        let killed = self.slice(cursor, end);
+        self.delete(cursor, end);
+
+        (killed, cursor)
+    }
+
+    /// Whether edits to this buffer are rejected.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.text.len_chars()
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.text.len_lines()
+    }
+
+    /// Char offset of the start of `line`.
+    pub fn line_to_char(&self, line: usize) -> usize {
+        self.text.line_to_char(line)
+    }
+
+    /// The line containing the char at `offset`.
+    pub fn char_to_line(&self, offset: usize) -> usize {
+        self.text.char_to_line(offset)
+    }
+
+    /// Byte offset of the char at `cursor`. The editor works in char
+    /// offsets; external protocols (and `&str` slicing) want bytes.
+    pub fn cursor_byte_offset(&self, cursor: usize) -> usize {
+        self.text.char_to_byte(cursor.min(self.text.len_chars()))
+    }
+
+    /// Byte offset of a `(line, column)` position, for LSP-style
+    /// integrations that index into the raw text.
+    pub fn position_to_byte(&self, position: Position) -> usize {
+        self.cursor_byte_offset(self.text.line_to_char(position.line) + position.column)
+    }
+
+    /// The `(line, column)` position of the char containing the byte at
+    /// `offset`.
+    pub fn byte_to_position(&self, offset: usize) -> Position {
+        let char_offset = self.text.byte_to_char(offset.min(self.text.len_bytes()));
+        let line = self.text.char_to_line(char_offset);
+
+        Position::new(line, char_offset - self.text.line_to_char(line))
+    }
+
+    /// UTF-16 code-unit offset of a `(line, column)` position. LSP
+    /// positions count UTF-16 code units, so chars outside the BMP —
+    /// emoji, for instance — count as two.
+    pub fn position_to_utf16(&self, position: Position) -> usize {
+        let char_offset = self.text.line_to_char(position.line) + position.column;
+        self.text.char_to_utf16_cu(char_offset.min(self.text.len_chars()))
+    }
+
+    /// Char offset of the UTF-16 code unit at `offset`; the inverse of
+    /// [`Buffer::position_to_utf16`].
+    pub fn utf16_to_char(&self, offset: usize) -> usize {
+        self.text.utf16_cu_to_char(offset.min(self.text.len_utf16_cu()))
+    }
+
+    /// Length of `line` in chars, not counting its trailing newline.
+    pub fn line_len(&self, line: usize) -> usize {
+        let slice = self.text.line(line);
+        let mut len = slice.len_chars();
+
+        if len > 0 && slice.char(len - 1) == '\n' {
+            len -= 1;
+        }
+
+        len
+    }
+
+    /// Opens a blank line below the cursor's line and returns the char
+    /// offset of its start, for Vim-style `o`. The cursor's column is
+    /// irrelevant and the line's own text is left intact, unlike a
+    /// newline inserted at the cursor, which splits the line there.
+    pub fn open_line_below(&mut self, cursor: (usize, usize)) -> usize {
+        let line = cursor.0.min(self.len_lines().saturating_sub(1));
+        let end = self.line_to_char(line) + self.line_len(line);
+
+        self.insert(end, "\n");
+        end + 1
+    }
+
+    /// Opens a blank line above the cursor's line and returns the char
+    /// offset of its start, for Vim-style `O`.
+    pub fn open_line_above(&mut self, cursor: (usize, usize)) -> usize {
+        let line = cursor.0.min(self.len_lines().saturating_sub(1));
+        let start = self.line_to_char(line);
+
+        self.insert(start, "\n");
+        start
+    }
+
+    /// The buffer's lines as owned strings, without trailing newlines.
+    /// Follows `len_lines` semantics: a buffer ending in `\n` yields a
+    /// final empty line, so frontends render the empty last row where the
+    /// cursor can sit, while one not ending in `\n` does not.
+    pub fn get_lines(&self) -> Vec<String> {
+        self.text
+            .lines()
+            .map(|line| {
+                let mut s = line.to_string();
+
+                if s.ends_with('\n') {
+                    s.pop();
+                }
+
+                s
+            })
+            .collect()
+    }
+
+    /// The text in the char range `start..end`, with both bounds clamped
+    /// to the buffer. An empty or inverted range yields an empty string.
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        let len = self.text.len_chars();
+        let start = start.min(len);
+        let end = end.min(len);
+
+        if start >= end {
+            return String::new();
+        }
+
+        self.text.slice(start..end).to_string()
+    }
+
+    /// Char offset of the next occurrence of `needle` at or after
+    /// `from`, wrapping to the start of the buffer when nothing follows.
+    /// `None` when the buffer holds no match at all, or the needle is
+    /// empty. The search materializes the contents, so a needle spanning
+    /// lines is found like any other.
+    pub fn search(&self, needle: &str, from: usize) -> Option<usize> {
+        let text = self.to_string();
+        let from_byte = self.text.char_to_byte(from.min(self.text.len_chars()));
+        let starts = match_starts(&text, needle);
+
+        starts
+            .iter()
+            .find(|&&start| start >= from_byte)
+            .or_else(|| starts.first())
+            .map(|&byte| self.text.byte_to_char(byte))
+    }
+
+    /// How many times `needle` occurs in the buffer, counting
+    /// overlapping occurrences: `"aa"` appears three times in `"aaaa"`.
+    /// An empty needle matches nowhere.
+    pub fn count_matches(&self, needle: &str) -> usize {
+        match_starts(&self.to_string(), needle).len()
+    }
+
+    /// How many matches of `needle` start strictly before char `at`,
+    /// counted the same way as [`count_matches`](Buffer::count_matches).
+    /// Adding one gives the match ordinal for "match N of M" displays.
+    pub fn matches_before(&self, needle: &str, at: usize) -> usize {
+        let at_byte = self.text.char_to_byte(at.min(self.text.len_chars()));
+
+        match_starts(&self.to_string(), needle)
+            .into_iter()
+            .filter(|&start| start < at_byte)
+            .count()
+    }
+
+    /// Char range `(start, end)` of the next regex match at or after
+    /// char `from`, wrapping like [`search`](Buffer::search).
+    ///
+    /// Because ropey stores the text in chunks, the contents are
+    /// materialized into one string per call rather than matching
+    /// across chunk boundaries; at the buffer sizes this editor targets
+    /// that is the simpler and fast-enough strategy. The search uses
+    /// `find_at` instead of slicing, so anchors keep their meaning:
+    /// `^` matches the start of the text (or of a line under `(?m)`),
+    /// never the arbitrary point the search resumed from.
+    pub fn search_regex(&self, regex: &Regex, from: usize) -> Option<(usize, usize)> {
+        let text = self.to_string();
+        let from_byte = self.text.char_to_byte(from.min(self.text.len_chars()));
+
+        let found = regex
+            .find_at(&text, from_byte)
+            .or_else(|| regex.find(&text))?;
+
+        Some((
+            self.text.byte_to_char(found.start()),
+            self.text.byte_to_char(found.end()),
+        ))
+    }
+
+    /// How many times `regex` matches in the buffer. Unlike
+    /// [`count_matches`](Buffer::count_matches), regex matches never
+    /// overlap: each one resumes past the previous match's end.
+    pub fn count_matches_regex(&self, regex: &Regex) -> usize {
+        regex.find_iter(&self.to_string()).count()
+    }
+
+    /// How many regex matches start strictly before char `at`, for the
+    /// match-ordinal display.
+    pub fn regex_matches_before(&self, regex: &Regex, at: usize) -> usize {
+        let at_byte = self.text.char_to_byte(at.min(self.text.len_chars()));
+
+        regex
+            .find_iter(&self.to_string())
+            .take_while(|found| found.start() < at_byte)
+            .count()
+    }
+
+    /// A cheap FNV-1a hash of the buffer's contents, streamed over the
+    /// rope's chunks without materializing the text. Identical content
+    /// hashes identically no matter how the rope is structured
+    /// internally, so pollers can skip reprocessing when it matches the
+    /// hash they last saw.
+    pub fn content_hash(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let mut hash = FNV_OFFSET;
+        for chunk in self.text.chunks() {
+            for byte in chunk.bytes() {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+
+        hash
+    }
+
+    /// Counts `(words, lines, chars, bytes)` over the char range, or the
+    /// whole buffer when `range` is `None`. Words are runs of
+    /// non-whitespace. Iterates rope chunks rather than allocating the
+    /// contents.
+    pub fn word_count(&self, range: Option<(usize, usize)>) -> (usize, usize, usize, usize) {
+        let slice = match range {
+            Some((start, end)) => self.text.slice(start..end),
+            None => self.text.slice(..),
+        };
+
+        let mut words = 0;
+        let mut in_word = false;
+
+        for chunk in slice.chunks() {
+            for c in chunk.chars() {
+                if c.is_whitespace() {
+                    in_word = false;
+                } else if !in_word {
+                    words += 1;
+                    in_word = true;
+                }
+            }
+        }
+
+        (words, slice.len_lines(), slice.len_chars(), slice.len_bytes())
+    }
+
+    /// The buffer's short display name: its `name` when one is set, the
+    /// backing file's basename otherwise. Every buffer the editor
+    /// creates has one or the other.
+    pub fn display_name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+
+        self.filepath
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Whether the buffer differs from its last-saved state. Derived
+    /// from the undo history's distance to the save point rather than a
+    /// sticky flag, so undoing every edit since the save makes the
+    /// buffer clean again.
+    pub fn is_modified(&self) -> bool {
+        self.saved_history_len != Some(self.history.len())
+    }
+
+    /// Writes the buffer back to `filepath`. Fails if the buffer has no
+    /// associated file. The rope is streamed to the file chunk by chunk,
+    /// so saving never materializes the whole buffer as one `String`.
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = self.filepath.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "buffer has no file path")
+        })?;
+
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+
+        if self.had_bom {
+            io::Write::write_all(&mut writer, "\u{FEFF}".as_bytes())?;
+        }
+
+        for chunk in self.text.chunks() {
+            io::Write::write_all(&mut writer, chunk.as_bytes())?;
+        }
+
+        io::Write::flush(&mut writer)?;
+        self.saved_history_len = Some(self.history.len());
+        self.remove_swap();
+
+        Ok(())
+    }
+
+    /// Everything needed to write this buffer out later, without a
+    /// reference to the buffer itself. Lets an embedder snapshot under a
+    /// lock and do the disk write outside it; pair with
+    /// [`write_snapshot`] and [`Buffer::mark_saved`]. `None` for buffers
+    /// with no backing file.
+    pub fn snapshot_for_save(&self) -> Option<(PathBuf, String)> {
+        let path = self.filepath.clone()?;
+
+        let mut contents = String::with_capacity(self.text.len_bytes() + 3);
+        if self.had_bom {
+            contents.push('\u{FEFF}');
+        }
+        for chunk in self.text.chunks() {
+            contents.push_str(chunk);
+        }
+
+        Some((path, contents))
+    }
+
+    /// Marks the buffer clean after its snapshot was written by
+    /// [`write_snapshot`], removing the swap file as a save does.
+    pub fn mark_saved(&mut self) {
+        self.saved_history_len = Some(self.history.len());
+        self.remove_swap();
+    }
+
+    /// Writes the chars in `range` — the whole buffer when `None` — to
+    /// a separate file, streaming chunk by chunk like [`Buffer::save`].
+    /// The buffer's own path and modified flag are left alone; this is
+    /// for extracting a snippet, not saving.
+    pub fn write_range_to(&self, range: Option<(usize, usize)>, path: &Path) -> io::Result<()> {
+        let (start, end) = range.unwrap_or((0, self.len_chars()));
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+
+        for chunk in self.text.slice(start..end).chunks() {
+            io::Write::write_all(&mut writer, chunk.as_bytes())?;
+        }
+
+        io::Write::flush(&mut writer)
+    }
+
+    /// Renames the backing file on disk and points the buffer at the
+    /// new path. The modified flag is left alone, so unsaved edits stay
+    /// unsaved against the new path. Fails if the buffer has no file,
+    /// or if the target already exists and `force` is not set.
+    pub fn rename(&mut self, new_path: &Path, force: bool) -> io::Result<()> {
+        let old_path = self.filepath.clone().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "buffer has no file path")
+        })?;
+
+        if !force && new_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", new_path.display()),
+            ));
+        }
+
+        // The swap file is keyed by the old name; drop it before the
+        // path changes and let the next swap tick recreate it.
+        self.remove_swap();
+
+        fs::rename(&old_path, new_path)?;
+        self.filepath = Some(new_path.to_path_buf());
+
+        Ok(())
+    }
+
+    /// Where this buffer's swap file lives: `.foo.txt.swp` next to
+    /// `foo.txt`. `None` for buffers with no backing file.
+    pub fn swap_path(&self) -> Option<PathBuf> {
+        let path = self.filepath.as_ref()?;
+        let name = path.file_name()?;
+
+        let mut swap_name = std::ffi::OsString::from(".");
+        swap_name.push(name);
+        swap_name.push(".swp");
+
+        Some(path.with_file_name(swap_name))
+    }
+
+    /// Writes the buffer's contents to its swap file so edits survive a
+    /// crash between saves. A no-op for buffers with no backing file.
+    pub fn write_swap(&self) -> io::Result<()> {
+        if let Some(swap) = self.swap_path() {
+            fs::write(swap, self.text.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the swap file, if any. Called after a clean save.
+    fn remove_swap(&self) {
+        if let Some(swap) = self.swap_path() {
+            let _ = fs::remove_file(swap);
+        }
+    }
+
+    /// Whether the backing file had a UTF-8 BOM when loaded.
+    pub fn had_bom(&self) -> bool {
+        self.had_bom
+    }
+
+    /// Whether the loaded contents used CRLF line endings. Scratch
+    /// buffers and fresh files are plain LF.
+    pub fn uses_crlf(&self) -> bool {
+        self.uses_crlf
+    }
+}
+
+/// Byte offsets where `needle` occurs in `text`, including overlapping
+/// occurrences: each match is followed by re-searching one char past its
+/// start, not past its end.
+fn match_starts(text: &str, needle: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+
+    if needle.is_empty() {
+        return starts;
+    }
+
+    let mut at = 0;
+    while let Some(i) = text[at..].find(needle) {
+        let found = at + i;
+        starts.push(found);
+
+        let step = text[found..]
+            .chars()
+            .next()
+            .map(char::len_utf8)
+            .unwrap_or(1);
+        at = found + step;
+    }
+
+    starts
+}
+
+/// Whether `text` contains a `\r\n` anywhere, scanning chunk-by-chunk so
+/// a pair split across a chunk boundary still counts.
+fn contains_crlf(text: &Rope) -> bool {
+    let mut prev = '\0';
+
+    for chunk in text.chunks() {
+        for c in chunk.chars() {
+            if prev == '\r' && c == '\n' {
+                return true;
+            }
+            prev = c;
+        }
+    }
+
+    false
+}
+
+/// Writes a snapshot taken by [`Buffer::snapshot_for_save`] to disk.
+pub fn write_snapshot(path: &Path, contents: &str) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+
+    io::Write::write_all(&mut writer, contents.as_bytes())?;
+    io::Write::flush(&mut writer)
+}
+
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn snapshot_saves_match_a_direct_save() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0xEF, 0xBB, 0xBF, b'h', b'i']).unwrap();
+
+        let mut buffer = Buffer::from_file(BufferId::new(0), file.path()).unwrap();
+        buffer.insert(2, "!");
+
+        let (path, contents) = buffer.snapshot_for_save().unwrap();
+        write_snapshot(&path, &contents).unwrap();
+        buffer.mark_saved();
+
+        // The BOM survives the snapshot path just like Buffer::save.
+        assert_eq!(
+            std::fs::read(file.path()).unwrap(),
+            &[0xEF, 0xBB, 0xBF, b'h', b'i', b'!']
+        );
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn from_file_strips_a_utf8_bom() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0xEF, 0xBB, 0xBF, b'h', b'i']).unwrap();
+
+        let buffer = Buffer::from_file(BufferId::new(0), file.path()).unwrap();
+
+        assert_eq!(buffer.to_string(), "hi");
+        assert_eq!(buffer.len_chars(), 2);
+        assert!(buffer.had_bom());
+    }
+
+    #[test]
+    fn save_reemits_the_bom() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0xEF, 0xBB, 0xBF, b'h', b'i']).unwrap();
+
+        let mut buffer = Buffer::from_file(BufferId::new(0), file.path()).unwrap();
+        buffer.insert(2, "!");
+        buffer.save().unwrap();
+
+        let bytes = fs::read(file.path()).unwrap();
+        assert_eq!(bytes, [0xEF, 0xBB, 0xBF, b'h', b'i', b'!']);
+    }
+
+    #[test]
+    fn a_crlf_file_with_a_bom_reports_its_flavor() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0xEF, 0xBB, 0xBF]).unwrap();
+        file.write_all(b"one\r\ntwo\r\n").unwrap();
+
+        let buffer = Buffer::from_file(BufferId::new(0), file.path()).unwrap();
+        assert!(buffer.had_bom());
+        assert!(buffer.uses_crlf());
+
+        // Scratch buffers are plain LF.
+        assert!(!Buffer::from_str(BufferId::new(1), "lf only\n").uses_crlf());
+    }
+
+    #[test]
+    fn from_reader_makes_an_unmodified_buffer_with_no_file() {
+        let mut buffer = Buffer::from_reader(BufferId::new(0), "piped in".as_bytes()).unwrap();
+
+        assert_eq!(buffer.to_string(), "piped in");
+        assert!(!buffer.is_modified());
+        assert!(buffer.filepath.is_none());
+        assert!(buffer.save().is_err_and(|e| e.kind() == io::ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn slice_extracts_a_char_range() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one two three");
+
+        assert_eq!(buffer.slice(4, 7), "two");
+    }
+
+    #[test]
+    fn slice_spans_newlines() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one\ntwo");
+
+        assert_eq!(buffer.slice(2, 5), "e\nt");
+    }
+
+    #[test]
+    fn slice_clamps_out_of_bounds_ranges() {
+        let buffer = Buffer::from_str(BufferId::new(0), "short");
+
+        assert_eq!(buffer.slice(2, 100), "ort");
+        assert_eq!(buffer.slice(100, 200), "");
+        assert_eq!(buffer.slice(3, 3), "");
+        assert_eq!(buffer.slice(4, 2), "");
+    }
+
+    #[test]
+    fn word_count_covers_the_whole_buffer() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one two\nthree\n");
+
+        assert_eq!(buffer.word_count(None), (3, 3, 14, 14));
+    }
+
+    #[test]
+    fn word_count_distinguishes_chars_and_bytes() {
+        // "héllo wörld" has multi-byte chars, so bytes > chars.
+        let buffer = Buffer::from_str(BufferId::new(0), "héllo wörld");
+
+        let (words, lines, chars, bytes) = buffer.word_count(None);
+        assert_eq!(words, 2);
+        assert_eq!(lines, 1);
+        assert_eq!(chars, 11);
+        assert_eq!(bytes, 13);
+    }
+
+    #[test]
+    fn word_count_respects_a_range() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one two three");
+
+        let (words, _, chars, _) = buffer.word_count(Some((4, 7)));
+        assert_eq!(words, 1);
+        assert_eq!(chars, 3);
+    }
+
+    #[test]
+    fn large_files_stream_in_with_the_right_line_count() {
+        let line = "0123456789abcdef ".repeat(16);
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for _ in 0..10_000 {
+            writeln!(file, "{}", line).unwrap();
+        }
+
+        let buffer = Buffer::from_file(BufferId::new(0), file.path()).unwrap();
+
+        // 10,000 lines plus the empty one after the final newline.
+        assert_eq!(buffer.len_lines(), 10_001);
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn invalid_utf8_names_the_file_in_the_error() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0xC3, 0x28]).unwrap();
+
+        let err = match Buffer::from_file(BufferId::new(0), file.path()) {
+            Err(err) => err,
+            Ok(_) => panic!("invalid UTF-8 should not load"),
+        };
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("not valid UTF-8"));
+    }
+
+    #[test]
+    fn saving_a_large_buffer_round_trips_byte_for_byte() {
+        // Several megabytes, so the rope spans many chunks.
+        let line = "0123456789abcdef ".repeat(16);
+        let contents: String = (0..10_000).map(|_| format!("{}\n", line)).collect();
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut buffer = Buffer::from_str(BufferId::new(0), &contents);
+        buffer.filepath = Some(file.path().to_path_buf());
+        buffer.insert(0, "x");
+
+        buffer.save().unwrap();
+
+        assert_eq!(
+            fs::read(file.path()).unwrap(),
+            format!("x{}", contents).into_bytes()
+        );
+    }
+
+    #[test]
+    fn a_trailing_newline_yields_a_final_empty_line() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one\ntwo\n");
+
+        assert_eq!(buffer.get_lines(), vec!["one", "two", ""]);
+        assert_eq!(buffer.len_lines(), 3);
+    }
+
+    #[test]
+    fn no_trailing_newline_yields_no_extra_line() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one\ntwo");
+
+        assert_eq!(buffer.get_lines(), vec!["one", "two"]);
+        assert_eq!(buffer.len_lines(), 2);
+    }
+
+    #[test]
+    fn kill_to_line_end_takes_the_rest_of_the_line() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "one two\nthree");
+
+        let (killed, cursor) = buffer.kill_to_line_end(3);
+
+        assert_eq!(killed, " two");
+        assert_eq!(cursor, 3);
+        assert_eq!(buffer.to_string(), "one\nthree");
+    }
+
+    #[test]
+    fn kill_at_line_end_joins_the_next_line() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "one\ntwo");
+
+        let (killed, _) = buffer.kill_to_line_end(3);
+
+        assert_eq!(killed, "\n");
+        assert_eq!(buffer.to_string(), "onetwo");
+    }
+
+    #[test]
+    fn kill_at_end_of_buffer_is_a_no_op() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "one");
+
+        let (killed, cursor) = buffer.kill_to_line_end(3);
+
+        assert_eq!(killed, "");
+        assert_eq!(cursor, 3);
+        assert_eq!(buffer.to_string(), "one");
+    }
+
+    #[test]
+    fn transpose_swaps_around_the_cursor() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "ba");
+
+        assert_eq!(buffer.transpose_chars(1), 2);
+        assert_eq!(buffer.to_string(), "ab");
+    }
+
+    #[test]
+    fn transpose_at_line_end_swaps_the_two_preceding_chars() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "ab\ncd");
+
+        assert_eq!(buffer.transpose_chars(2), 2);
+        assert_eq!(buffer.to_string(), "ba\ncd");
+    }
+
+    #[test]
+    fn transpose_handles_multibyte_chars_and_buffer_edges() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "éa");
+
+        assert_eq!(buffer.transpose_chars(1), 2);
+        assert_eq!(buffer.to_string(), "aé");
+
+        // Start of buffer is a no-op.
+        assert_eq!(buffer.transpose_chars(0), 0);
+        assert_eq!(buffer.to_string(), "aé");
+    }
+
+    #[test]
+    fn edits_write_a_swap_file_and_save_removes_it() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hi").unwrap();
+
+        let mut buffer = Buffer::from_file(BufferId::new(0), file.path()).unwrap();
+        buffer.insert(2, "!");
+        buffer.write_swap().unwrap();
+
+        let swap = buffer.swap_path().unwrap();
+        assert!(swap.file_name().unwrap().to_str().unwrap().ends_with(".swp"));
+        assert_eq!(fs::read_to_string(&swap).unwrap(), "hi!");
+
+        buffer.save().unwrap();
+        assert!(!swap.exists());
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip_edits() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "hello");
+        buffer.insert(5, " world");
+        buffer.delete(0, 1);
+
+        assert_eq!(buffer.undo(), Some(1));
+        assert_eq!(buffer.to_string(), "hello world");
+        assert!(buffer.undo().is_some());
+        assert_eq!(buffer.to_string(), "hello");
+        assert!(buffer.undo().is_none());
+
+        assert!(buffer.redo().is_some());
+        assert!(buffer.redo().is_some());
+        assert_eq!(buffer.to_string(), "ello world");
+        assert!(buffer.redo().is_none());
+    }
+
+    #[test]
+    fn a_fresh_edit_clears_the_redo_stack() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "");
+        buffer.insert(0, "a");
+        buffer.undo();
+        buffer.insert(0, "b");
+
+        assert!(buffer.redo().is_none());
+        assert_eq!(buffer.to_string(), "b");
+    }
+
+    #[test]
+    fn grouped_edits_undo_in_one_step() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "ab");
+        buffer.begin_edit_group();
+        buffer.insert(0, "x");
+        buffer.insert(2, "y");
+        buffer.end_edit_group();
+
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "ab");
+    }
+
+    #[test]
+    fn the_history_cap_drops_the_oldest_units() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "");
+        buffer.set_history_limit(2);
+        buffer.insert(0, "a");
+        buffer.insert(1, "b");
+        buffer.insert(2, "c");
+
+        assert!(buffer.undo().is_some());
+        assert!(buffer.undo().is_some());
+        assert!(buffer.undo().is_none(), "the first edit fell off");
+        assert_eq!(buffer.to_string(), "a");
+    }
+
+    #[test]
+    fn delete_word_backward_eats_the_word_before_the_cursor() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "hello world");
+
+        let (killed, cursor) = buffer.delete_word_backward(11);
+
+        assert_eq!(buffer.to_string(), "hello ");
+        assert_eq!(killed, "world");
+        assert_eq!(cursor, 6);
+
+        // From the gap, the whitespace and the word before it go
+        // together, as Emacs does it.
+        let (killed, cursor) = buffer.delete_word_backward(6);
+        assert_eq!(buffer.to_string(), "");
+        assert_eq!(killed, "hello ");
+        assert_eq!(cursor, 0);
+    }
+
+    #[test]
+    fn delete_word_forward_takes_the_next_word_and_leaves_the_cursor() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "hello world");
+
+        let (killed, cursor) = buffer.delete_word_forward(5);
+
+        assert_eq!(buffer.to_string(), "hello");
+        assert_eq!(killed, " world");
+        assert_eq!(cursor, 5);
+    }
+
+    #[test]
+    fn word_deletion_is_a_noop_at_the_buffer_edges() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "hi");
+
+        assert_eq!(buffer.delete_word_backward(0), (String::new(), 0));
+        assert_eq!(buffer.delete_word_forward(2), (String::new(), 2));
+        assert_eq!(buffer.to_string(), "hi");
+    }
+
+    #[test]
+    fn match_counting_includes_overlapping_occurrences() {
+        let buffer = Buffer::from_str(BufferId::new(0), "aaaa");
+
+        assert_eq!(buffer.count_matches("aa"), 3);
+        assert_eq!(buffer.count_matches("aaaa"), 1);
+        assert_eq!(buffer.count_matches("b"), 0);
+        assert_eq!(buffer.count_matches(""), 0);
+
+        assert_eq!(buffer.matches_before("aa", 0), 0);
+        assert_eq!(buffer.matches_before("aa", 2), 2);
+    }
+
+    #[test]
+    fn search_wraps_around_the_end_of_the_buffer() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one two one\n");
+
+        assert_eq!(buffer.search("one", 0), Some(0));
+        assert_eq!(buffer.search("one", 1), Some(8));
+        // Nothing after the last match: wrap back to the first.
+        assert_eq!(buffer.search("one", 9), Some(0));
+        assert_eq!(buffer.search("missing", 0), None);
+    }
+
+    #[test]
+    fn regex_anchors_keep_their_meaning_mid_buffer() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one two\ntwo\n");
+
+        // `^` without multiline only ever matches the very start, even
+        // when the search resumes further in (and wraps).
+        let anchored = Regex::new("^two").unwrap();
+        assert_eq!(buffer.search_regex(&anchored, 1), None);
+
+        let multiline = Regex::new("(?m)^two").unwrap();
+        assert_eq!(buffer.search_regex(&multiline, 1), Some((8, 11)));
+        // Past the last match it wraps to the first line-anchored one.
+        assert_eq!(buffer.search_regex(&multiline, 9), Some((8, 11)));
+    }
+
+    #[test]
+    fn regex_matches_iterate_without_overlapping() {
+        let buffer = Buffer::from_str(BufferId::new(0), "aaaa");
+        let regex = Regex::new("aa").unwrap();
+
+        // The literal counter sees three overlapping "aa"s here; the
+        // regex iterator resumes past each match's end and sees two.
+        assert_eq!(buffer.count_matches_regex(&regex), 2);
+        assert_eq!(buffer.search_regex(&regex, 1), Some((1, 3)));
+        // The iterator's matches start at 0 and 2, both before char 3.
+        assert_eq!(buffer.regex_matches_before(&regex, 3), 2);
+    }
+
+    #[test]
+    fn the_content_hash_depends_only_on_the_text() {
+        let text = "fn main() {\n    println!(\"hello\");\n}\n".repeat(50);
+
+        // Build the same content two ways: one bulk insert versus many
+        // small ones, so the ropes end up chunked differently.
+        let bulk = Buffer::from_str(BufferId::new(0), &text);
+        let mut piecemeal = Buffer::new(BufferId::new(1));
+        for (i, c) in text.chars().enumerate() {
+            piecemeal.insert(i, &c.to_string());
+        }
+
+        assert_eq!(bulk.to_string(), piecemeal.to_string());
+        assert_eq!(bulk.content_hash(), piecemeal.content_hash());
+
+        let mut changed = Buffer::from_str(BufferId::new(2), &text);
+        changed.insert(0, "x");
+        assert_ne!(changed.content_hash(), bulk.content_hash());
+    }
+
+    #[test]
+    fn paragraph_motion_jumps_between_blank_line_boundaries() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one\ntwo\n\nthree\n\n\nfour\n");
+
+        assert_eq!(buffer.move_paragraph_forward(0), Some(8));
+        assert_eq!(buffer.move_paragraph_forward(8), Some(15));
+        // The double blank run is one boundary; past the last paragraph
+        // the motion lands at the end of the buffer.
+        assert_eq!(buffer.move_paragraph_forward(15), Some(22));
+        assert_eq!(buffer.move_paragraph_forward(22), None);
+
+        assert_eq!(buffer.move_paragraph_backward(22), Some(16));
+        assert_eq!(buffer.move_paragraph_backward(16), Some(8));
+        assert_eq!(buffer.move_paragraph_backward(8), Some(0));
+        assert_eq!(buffer.move_paragraph_backward(0), None);
+    }
+
+    #[test]
+    fn whitespace_only_lines_count_as_paragraph_boundaries() {
+        let buffer = Buffer::from_str(BufferId::new(0), "one\n  \ntwo\n");
+
+        assert_eq!(buffer.move_paragraph_forward(0), Some(4));
+    }
+
+    #[test]
+    fn collapse_blank_lines_squeezes_runs_and_the_trailing_newline() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "a\n\n\n\nb\n\n\n");
+
+        buffer.collapse_blank_lines();
+
+        assert_eq!(buffer.to_string(), "a\n\nb\n");
+    }
+
+    #[test]
+    fn collapse_blank_lines_adds_the_missing_final_newline() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "a\nb");
+
+        buffer.collapse_blank_lines();
+
+        assert_eq!(buffer.to_string(), "a\nb\n");
+    }
+
+    #[test]
+    fn collapse_blank_lines_undoes_in_one_step() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "a\n\n\n\nb\n\n\n");
+
+        buffer.collapse_blank_lines();
+        buffer.undo();
+
+        assert_eq!(buffer.to_string(), "a\n\n\n\nb\n\n\n");
+    }
+
+    #[test]
+    fn undoing_back_to_the_save_point_clears_the_modified_flag() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut buffer = Buffer::from_file(BufferId::new(0), file.path()).unwrap();
+
+        buffer.insert(0, "a");
+        buffer.save().unwrap();
+        assert!(!buffer.is_modified());
+
+        buffer.insert(1, "b");
+        assert!(buffer.is_modified());
+
+        // Undo back to what's on disk.
+        buffer.undo();
+        assert!(!buffer.is_modified());
+
+        // Redo past the save point dirties the buffer again...
+        buffer.redo();
+        assert!(buffer.is_modified());
+
+        // ...and so does undoing to before it.
+        buffer.undo();
+        buffer.undo();
+        assert!(buffer.is_modified());
+    }
+
+    #[test]
+    fn a_fresh_edit_after_undo_orphans_the_save_point() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "");
+        buffer.insert(0, "a");
+        buffer.insert(1, "b");
+        buffer.mark_saved();
+
+        buffer.undo();
+        buffer.undo();
+        buffer.insert(0, "c");
+
+        // The history is as long as it was at the save, but the saved
+        // contents are on a discarded branch, so the buffer stays dirty.
+        buffer.insert(1, "d");
+        assert!(buffer.is_modified());
+    }
+
+    #[test]
+    fn sort_lines_orders_the_whole_buffer() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "banana\napple\ncherry");
+
+        buffer.sort_lines(None, false, false);
+
+        assert_eq!(buffer.to_string(), "apple\nbanana\ncherry");
+    }
+
+    #[test]
+    fn sort_lines_keeps_the_trailing_newline_structure() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "b\na\n");
+
+        buffer.sort_lines(None, false, false);
+
+        assert_eq!(buffer.to_string(), "a\nb\n");
+    }
+
+    #[test]
+    fn sort_lines_only_touches_the_given_range() {
+        // Chars 4..12 cover the middle two lines.
+        let mut buffer = Buffer::from_str(BufferId::new(0), "top\nccc\nbbb\nbottom\n");
+
+        buffer.sort_lines(Some((4, 12)), false, false);
+
+        assert_eq!(buffer.to_string(), "top\nbbb\nccc\nbottom\n");
+    }
+
+    #[test]
+    fn sort_lines_reverses_and_ignores_case_on_request() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "Banana\napple\n");
+        buffer.sort_lines(None, true, false);
+        assert_eq!(buffer.to_string(), "apple\nBanana\n");
+
+        let mut buffer = Buffer::from_str(BufferId::new(0), "a\nc\nb\n");
+        buffer.sort_lines(None, false, true);
+        assert_eq!(buffer.to_string(), "c\nb\na\n");
+    }
+
+    #[test]
+    fn a_sort_undoes_in_one_step() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "b\na\n");
+
+        buffer.sort_lines(None, false, false);
+        assert!(buffer.undo().is_some());
+
+        assert_eq!(buffer.to_string(), "b\na\n");
+        assert!(buffer.undo().is_none());
+    }
+
+    #[test]
+    fn byte_char_and_utf16_offsets_diverge_on_multibyte_content() {
+        // Line 0 holds a two-byte char, line 1 a four-byte emoji that
+        // is two UTF-16 code units.
+        let buffer = Buffer::from_str(BufferId::new(0), "héllo\na😀b\n");
+
+        // The 'b' after the emoji: char 8, byte 12, UTF-16 unit 9.
+        let b = Position::new(1, 2);
+        assert_eq!(buffer.position_to_byte(b), 12);
+        assert_eq!(buffer.cursor_byte_offset(8), 12);
+        assert_eq!(buffer.byte_to_position(12), b);
+        assert_eq!(buffer.position_to_utf16(b), 9);
+        assert_eq!(buffer.utf16_to_char(9), 8);
+    }
+
+    #[test]
+    fn offset_accessors_clamp_past_the_end() {
+        let buffer = Buffer::from_str(BufferId::new(0), "hi");
+
+        assert_eq!(buffer.cursor_byte_offset(99), 2);
+        assert_eq!(buffer.byte_to_position(99), Position::new(0, 2));
+        assert_eq!(buffer.utf16_to_char(99), 2);
+    }
+
+    #[test]
+    fn open_line_below_leaves_the_current_line_intact() {
+        let mut buffer = Buffer::from_str(BufferId(0), "one\ntwo\n");
+
+        // Mid-line cursor; the column must not split "one".
+        let offset = buffer.open_line_below((0, 1));
+
+        assert_eq!(buffer.to_string(), "one\n\ntwo\n");
+        assert_eq!(offset, 4, "cursor offset is the new blank line");
+    }
+
+    #[test]
+    fn open_line_below_appends_past_a_final_line_without_newline() {
+        let mut buffer = Buffer::from_str(BufferId(0), "one");
+
+        let offset = buffer.open_line_below((0, 3));
+
+        assert_eq!(buffer.to_string(), "one\n");
+        assert_eq!(offset, 4);
+    }
+
+    #[test]
+    fn open_line_above_puts_the_blank_line_before_the_cursor_line() {
+        let mut buffer = Buffer::from_str(BufferId(0), "one\ntwo\n");
+
+        let offset = buffer.open_line_above((1, 2));
+
+        assert_eq!(buffer.to_string(), "one\n\ntwo\n");
+        assert_eq!(offset, 4);
+    }
+
+    #[test]
+    fn uncommented_lines_gain_the_token_after_their_indentation() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "fn main() {\n    body();\n}\n");
+
+        buffer.toggle_line_comment(None, "// ");
+
+        assert_eq!(
+            buffer.to_string(),
+            "// fn main() {\n    // body();\n// }\n"
+        );
+    }
+
+    #[test]
+    fn fully_commented_lines_lose_the_token() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "// one\n    // two\n");
+
+        buffer.toggle_line_comment(None, "// ");
+
+        assert_eq!(buffer.to_string(), "one\n    two\n");
+    }
+
+    #[test]
+    fn a_mixed_region_comments_everything() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "# done\ntodo\n");
+
+        buffer.toggle_line_comment(None, "# ");
+
+        assert_eq!(buffer.to_string(), "# # done\n# todo\n");
+    }
+
+    #[test]
+    fn blank_lines_are_left_alone_when_commenting() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "a\n\nb\n");
+
+        buffer.toggle_line_comment(None, "# ");
+
+        assert_eq!(buffer.to_string(), "# a\n\n# b\n");
+    }
+
+    #[test]
+    fn renaming_moves_the_file_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_path = dir.path().join("old.txt");
+        fs::write(&old_path, "contents").unwrap();
+
+        let mut buffer = Buffer::from_file(BufferId::new(0), &old_path).unwrap();
+        let new_path = dir.path().join("new.txt");
+        buffer.rename(&new_path, false).unwrap();
+
+        assert!(!old_path.exists());
+        assert_eq!(fs::read_to_string(&new_path).unwrap(), "contents");
+        assert_eq!(buffer.filepath.as_deref(), Some(new_path.as_path()));
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn renaming_refuses_to_clobber_unless_forced() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_path = dir.path().join("old.txt");
+        let taken = dir.path().join("taken.txt");
+        fs::write(&old_path, "mine").unwrap();
+        fs::write(&taken, "theirs").unwrap();
+
+        let mut buffer = Buffer::from_file(BufferId::new(0), &old_path).unwrap();
+
+        let err = buffer.rename(&taken, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&taken).unwrap(), "theirs");
+
+        buffer.rename(&taken, true).unwrap();
+        assert_eq!(fs::read_to_string(&taken).unwrap(), "mine");
+    }
+
+    #[test]
+    fn renaming_a_pathless_buffer_fails() {
+        let mut buffer = Buffer::from_str(BufferId::new(0), "scratch");
+
+        let err = buffer.rename(Path::new("/tmp/nope.txt"), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn files_without_a_bom_are_untouched() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hi").unwrap();
+
+        let buffer = Buffer::from_file(BufferId::new(0), file.path()).unwrap();
+
+        assert_eq!(buffer.to_string(), "hi");
+        assert!(!buffer.had_bom());
+    }
+}
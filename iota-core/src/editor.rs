@@ -0,0 +1,2576 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::buffer::{Buffer, BufferId};
+use crate::input::{Direction, EditorError, EditorEvent, EditorInput, SearchKind};
+use crate::session::{Session, SessionFile, ViewStateCache};
+use crate::view::View;
+
+/// The register kills and yanks use when none is named.
+const UNNAMED_REGISTER: char = '"';
+
+/// The top-level editing session: a set of buffers, the views onto them,
+/// and the command dispatcher.
+pub struct Editor {
+    buffers: Vec<Buffer>,
+    views: Vec<View>,
+    current_view: usize,
+    /// View indices shown side by side, left to right. Always holds at
+    /// least one entry, and `windows[focused_window] == current_view`.
+    windows: Vec<usize>,
+    focused_window: usize,
+    /// Frame size as last reported by the frontend, divided between the
+    /// windows on relayout.
+    frame: (usize, usize),
+    next_buffer_id: usize,
+    /// Counter behind the `*scratch-N*` names handed to nameless
+    /// buffers.
+    next_scratch: usize,
+    /// Killed text waiting to be yanked, keyed by register name. Plain
+    /// kills and yanks use [`UNNAMED_REGISTER`]; the select-register
+    /// prefix targets any other.
+    registers: HashMap<char, String>,
+    /// Register the next kill or yank goes through, consumed on use.
+    active_register: Option<char>,
+    /// Set between the select-register command and the char naming the
+    /// register.
+    selecting_register: bool,
+    /// Set when a quit was refused because of unsaved changes; a second
+    /// quit request while this is set goes through.
+    pending_quit: bool,
+    /// Same, for closing a modified buffer.
+    pending_delete: bool,
+    /// Count being accumulated by a universal argument; the next command
+    /// runs that many times. `Some(0)` right after `C-u`, before any
+    /// digit.
+    pending_count: Option<usize>,
+    /// When set, typed chars replace the char under the cursor instead of
+    /// pushing it right. Toggled by the Insert key.
+    overwrite: bool,
+    /// The last buffer-changing command, for the repeat command. A run
+    /// of typed chars is coalesced into one `Paste` so repeating it
+    /// replays the whole run. Movement never overwrites this.
+    last_change: Option<EditorInput>,
+    /// Whether `last_change` is a typed-char run still being extended.
+    /// Any non-insert command closes the run.
+    last_change_is_typing: bool,
+    /// Cursor positions of files edited earlier, updated on save and
+    /// buffer close, so reopening a file comes back to the same place.
+    view_state: ViewStateCache,
+    /// The active search term and how to interpret it, set by `Search`
+    /// and reused by `SearchNext`. The match count is recomputed per
+    /// search, so it stays honest as the buffer changes.
+    search_term: Option<(String, SearchKind)>,
+}
+
+impl Editor {
+    /// Creates an editor with a single empty scratch buffer.
+    pub fn new() -> Editor {
+        let mut editor = Editor {
+            buffers: Vec::new(),
+            views: Vec::new(),
+            current_view: 0,
+            windows: Vec::new(),
+            focused_window: 0,
+            frame: (0, 0),
+            next_buffer_id: 0,
+            next_scratch: 1,
+            registers: HashMap::new(),
+            active_register: None,
+            selecting_register: false,
+            pending_quit: false,
+            pending_delete: false,
+            pending_count: None,
+            overwrite: false,
+            last_change: None,
+            last_change_is_typing: false,
+            view_state: ViewStateCache::default(),
+            search_term: None,
+        };
+
+        let id = editor.allocate_buffer_id();
+        editor.create_buffer_with_view(Buffer::new(id));
+
+        editor
+    }
+
+    /// Hands out the next unused [`BufferId`]. Embedders constructing
+    /// their own [`Buffer`]s should get ids here so they never collide
+    /// with the editor's.
+    pub fn allocate_buffer_id(&mut self) -> BufferId {
+        let id = BufferId::new(self.next_buffer_id);
+        self.next_buffer_id += 1;
+        id
+    }
+
+    pub fn buffers(&self) -> &[Buffer] {
+        &self.buffers
+    }
+
+    /// Every view in the session, in creation order. The indices are the
+    /// view handles [`Editor::focus`] accepts.
+    pub fn views(&self) -> &[View] {
+        &self.views
+    }
+
+    /// Makes the view at `index` current. Out-of-range indices are
+    /// ignored.
+    pub fn focus(&mut self, index: usize) {
+        if index < self.views.len() {
+            self.set_current_view(index);
+        }
+    }
+
+    /// The view indices visible side by side, left to right.
+    pub fn windows(&self) -> &[usize] {
+        &self.windows
+    }
+
+    /// Index into [`Editor::windows`] of the window holding focus.
+    pub fn focused_window(&self) -> usize {
+        self.focused_window
+    }
+
+    /// Points the focused window (and `current_view`) at the view at
+    /// `index`.
+    fn set_current_view(&mut self, index: usize) {
+        self.current_view = index;
+
+        if self.windows.is_empty() {
+            self.windows.push(index);
+            self.focused_window = 0;
+        } else {
+            self.windows[self.focused_window] = index;
+        }
+    }
+
+    pub fn current_view(&self) -> &View {
+        &self.views[self.current_view]
+    }
+
+    pub fn current_view_mut(&mut self) -> &mut View {
+        &mut self.views[self.current_view]
+    }
+
+    pub fn current_buffer(&self) -> &Buffer {
+        let id = self.current_view().buffer_id;
+        self.buffer(id).expect("current view points at a live buffer")
+    }
+
+    pub fn current_buffer_mut(&mut self) -> &mut Buffer {
+        let id = self.current_view().buffer_id;
+        self.buffers
+            .iter_mut()
+            .find(|b| b.id() == id)
+            .expect("current view points at a live buffer")
+    }
+
+    pub fn buffer(&self, id: BufferId) -> Option<&Buffer> {
+        self.buffers.iter().find(|b| b.id() == id)
+    }
+
+    pub fn buffer_mut(&mut self, id: BufferId) -> Option<&mut Buffer> {
+        self.buffers.iter_mut().find(|b| b.id() == id)
+    }
+
+    /// Adds `buffer` to the editor along with a fresh view onto it, and
+    /// makes that view current. This is the library entry point for
+    /// embedders that build buffers themselves; `buffer`'s id should come
+    /// from [`Editor::allocate_buffer_id`].
+    pub fn open_buffer(&mut self, buffer: Buffer) -> BufferId {
+        self.create_buffer_with_view(buffer)
+    }
+
+    /// Adds `buffer` to the editor along with a fresh view onto it, and
+    /// makes that view current.
+    pub(crate) fn create_buffer_with_view(&mut self, mut buffer: Buffer) -> BufferId {
+        // Nameless scratch buffers get distinct names so the status line
+        // and buffer lists can tell them apart. The name is display-only;
+        // it is never treated as a path.
+        if buffer.name.is_none() && buffer.filepath.is_none() {
+            buffer.name = Some(format!("*scratch-{}*", self.next_scratch));
+            self.next_scratch += 1;
+        }
+
+        let id = buffer.id();
+        self.buffers.push(buffer);
+        self.views.push(View::new(id));
+        self.set_current_view(self.views.len() - 1);
+        id
+    }
+
+    /// Looks for a buffer already holding the file at `path`, comparing
+    /// canonicalized paths so `./foo` and `foo` match.
+    fn find_buffer_for_path(&self, path: &Path) -> Option<BufferId> {
+        let canonical = fs::canonicalize(path).ok()?;
+
+        self.buffers
+            .iter()
+            .find(|b| {
+                b.filepath
+                    .as_ref()
+                    .and_then(|p| fs::canonicalize(p).ok())
+                    .is_some_and(|p| p == canonical)
+            })
+            .map(|b| b.id())
+    }
+
+    fn open_file(&mut self, path: &Path) -> io::Result<EditorEvent> {
+        if let Some(id) = self.find_buffer_for_path(path) {
+            self.views.push(View::new(id));
+            self.set_current_view(self.views.len() - 1);
+
+            return Ok(EditorEvent::Info("Switched to already-open file".into()));
+        }
+
+        // Opening a file from a pristine scratch buffer — empty,
+        // unmodified, no backing file — loads it into that buffer in
+        // place, so starting the editor and opening a file doesn't leave
+        // a stray empty scratch behind.
+        let current = self.current_buffer();
+        let reuse = current.len_chars() == 0 && !current.is_modified() && current.filepath.is_none();
+
+        let id = if reuse {
+            current.id()
+        } else {
+            self.allocate_buffer_id()
+        };
+
+        let buffer = Buffer::from_file(id, path)?;
+        let stale_swap = swap_is_newer(&buffer, path);
+
+        if reuse {
+            *self.buffer_mut(id).expect("current buffer exists") = buffer;
+        } else {
+            self.create_buffer_with_view(buffer);
+        }
+
+        // Come back to wherever the cursor was the last time this file
+        // was open, clamped in case the file shrank since.
+        if let Some((line, column)) = self.view_state.lookup(&canonical_path(path)) {
+            let cursor = self.position_to_cursor(line, column);
+            let max_line = self.last_line();
+            let view = self.current_view_mut();
+            view.cursor = cursor;
+            view.adjust_scroll(max_line);
+        }
+
+        if stale_swap {
+            return Ok(EditorEvent::Error(EditorError::StaleSwap(
+                path.to_path_buf(),
+            )));
+        }
+
+        Ok(EditorEvent::Render)
+    }
+
+    /// Creates a buffer with `contents` and the display name `name`, but
+    /// no backing file, and switches to it.
+    pub fn open_scratch(&mut self, name: &str, contents: &str) -> BufferId {
+        let id = self.allocate_buffer_id();
+        let mut buffer = Buffer::from_str(id, contents);
+        buffer.name = Some(name.to_string());
+        self.create_buffer_with_view(buffer)
+    }
+
+    /// Snapshots the file-backed buffers into a [`Session`]: each
+    /// buffer's path plus the cursor and scroll of its first view.
+    /// Scratch buffers have nothing to reopen and are skipped.
+    pub fn save_session(&self) -> Session {
+        let files = self
+            .buffers
+            .iter()
+            .filter_map(|buffer| {
+                let path = buffer.filepath.clone()?;
+                let view = self.views.iter().find(|v| v.buffer_id == buffer.id())?;
+
+                Some(SessionFile {
+                    path,
+                    cursor: view.cursor,
+                    scroll_line: view.scroll_line,
+                })
+            })
+            .collect();
+
+        Session { files }
+    }
+
+    /// Reopens the files recorded in `session` and puts each view's
+    /// cursor and scroll back where they were, clamped to the file's
+    /// current contents. Files that no longer open are skipped rather
+    /// than failing the whole restore.
+    pub fn restore_session(&mut self, session: &Session) {
+        for file in &session.files {
+            if self.open_file(&file.path).is_err() {
+                continue;
+            }
+
+            let cursor = self.position_to_cursor(file.cursor.0, file.cursor.1);
+            let max_line = self.current_buffer().len_lines().saturating_sub(1);
+            let view = self.current_view_mut();
+            view.cursor = cursor;
+            view.set_scroll_line(file.scroll_line, max_line);
+            view.adjust_scroll(max_line);
+        }
+    }
+
+    /// Records the current view's cursor for its buffer's file in the
+    /// view-state cache, so a later open of the same file comes back to
+    /// it. Runs on save and buffer close; scratch buffers have no path
+    /// to remember.
+    pub fn remember_view_state(&mut self) {
+        if let Some(path) = self.current_buffer().filepath.as_deref() {
+            let path = canonical_path(path);
+            let cursor = self.current_view().cursor;
+            self.view_state.remember(path, cursor);
+        }
+    }
+
+    /// The remembered per-file cursor positions, for persisting
+    /// alongside the session.
+    pub fn view_state(&self) -> &ViewStateCache {
+        &self.view_state
+    }
+
+    /// Replaces the view-state cache, typically with one loaded from
+    /// disk before any files are opened.
+    pub fn restore_view_state(&mut self, view_state: ViewStateCache) {
+        self.view_state = view_state;
+    }
+
+    /// Char offset of the current view's cursor in its buffer.
+    fn cursor_offset(&self) -> usize {
+        let (line, column) = self.current_view().cursor;
+        self.current_buffer().line_to_char(line) + column
+    }
+
+    /// Converts a char offset in the current buffer back to a `(line,
+    /// column)` cursor.
+    fn offset_to_cursor(&self, offset: usize) -> (usize, usize) {
+        let buffer = self.current_buffer();
+        let offset = offset.min(buffer.len_chars());
+        let line = buffer.char_to_line(offset);
+        (line, offset - buffer.line_to_char(line))
+    }
+
+    /// Last line index of the current buffer, which scroll adjustment
+    /// needs for clamping at the bottom.
+    fn last_line(&self) -> usize {
+        self.current_buffer().len_lines().saturating_sub(1)
+    }
+
+    /// Clamps an arbitrary `(line, column)` to a valid cursor position in
+    /// the current buffer.
+    fn position_to_cursor(&self, line: usize, column: usize) -> (usize, usize) {
+        let buffer = self.current_buffer();
+        let line = line.min(buffer.len_lines().saturating_sub(1));
+        (line, column.min(buffer.line_len(line)))
+    }
+
+    /// Clamps the cursor and selection anchor of every view onto
+    /// `buffer_id` back inside the buffer. Edits through one view can
+    /// shrink or reshape the buffer out from under other views, and
+    /// ropey panics on out-of-range indices, so this runs after every
+    /// mutating command.
+    fn clamp_view_cursors(&mut self, id: BufferId) {
+        if self.buffer(id).is_none() {
+            return;
+        }
+
+        for i in 0..self.views.len() {
+            if self.views[i].buffer_id != id {
+                continue;
+            }
+
+            let (cursor, anchor) = {
+                let buffer = self.buffer(id).expect("checked above");
+                let max_line = buffer.len_lines().saturating_sub(1);
+                let clamp = |(line, column): (usize, usize)| {
+                    let line = line.min(max_line);
+                    (line, column.min(buffer.line_len(line)))
+                };
+
+                (
+                    clamp(self.views[i].cursor),
+                    self.views[i].selection_anchor.map(clamp),
+                )
+            };
+
+            self.views[i].cursor = cursor;
+            self.views[i].selection_anchor = anchor;
+
+            let len = self.buffer(id).expect("checked above").len_chars();
+            for offset in &mut self.views[i].secondary_cursors {
+                *offset = (*offset).min(len);
+            }
+            self.views[i].secondary_cursors.dedup();
+        }
+    }
+
+    /// Inserts `text` at the primary cursor and every secondary caret.
+    /// Carets are processed in ascending offset order with a running
+    /// delta, so an insertion never shifts a caret that hasn't been
+    /// processed yet.
+    fn insert_at_cursors(&mut self, text: &str) {
+        let id = self.current_view().buffer_id;
+        let primary = self.cursor_offset();
+        let inserted = text.chars().count();
+
+        let mut carets: Vec<(usize, bool)> = self
+            .current_view()
+            .secondary_cursors
+            .iter()
+            .map(|&offset| (offset, false))
+            .collect();
+        carets.push((primary, true));
+        carets.sort_unstable();
+
+        // Overwrite mode consumes the char that was under each caret,
+        // unless it is a newline or the caret sits at the end of the
+        // buffer. Line breaks always insert.
+        let overwrite = self.overwrite && !text.contains('\n');
+
+        let mut delta = 0;
+        let mut new_primary = primary;
+        let mut new_secondary = Vec::with_capacity(carets.len() - 1);
+
+        // The whole multi-caret insert is one undo unit.
+        self.current_buffer_mut().begin_edit_group();
+
+        for (offset, is_primary) in carets {
+            let at = offset + delta;
+            self.current_buffer_mut().insert(at, text);
+            delta += inserted;
+
+            if overwrite {
+                let buffer = self.current_buffer_mut();
+                let displaced = at + inserted;
+
+                if buffer
+                    .slice(displaced, displaced + 1)
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c != '\n')
+                {
+                    buffer.delete(displaced, displaced + 1);
+                    delta -= 1;
+                }
+            }
+
+            if is_primary {
+                new_primary = at + inserted;
+            } else {
+                new_secondary.push(at + inserted);
+            }
+        }
+
+        self.current_buffer_mut().end_edit_group();
+
+        let cursor = self.offset_to_cursor(new_primary);
+        let max_line = self.last_line();
+        let view = self.current_view_mut();
+        view.secondary_cursors = new_secondary;
+        view.cursor = cursor;
+        view.adjust_scroll(max_line);
+        self.clamp_view_cursors(id);
+    }
+
+    /// Deletes the char before the primary cursor and every secondary
+    /// caret, with the same ascending-order delta bookkeeping as
+    /// [`Editor::insert_at_cursors`]. Carets at the start of the buffer
+    /// stay put; carets that collapse onto each other merge.
+    fn delete_at_cursors(&mut self) {
+        let id = self.current_view().buffer_id;
+        let primary = self.cursor_offset();
+
+        let mut carets: Vec<(usize, bool)> = self
+            .current_view()
+            .secondary_cursors
+            .iter()
+            .map(|&offset| (offset, false))
+            .collect();
+        carets.push((primary, true));
+        carets.sort_unstable();
+
+        let mut removed = 0;
+        let mut new_primary = primary;
+        let mut new_secondary = Vec::with_capacity(carets.len() - 1);
+
+        self.current_buffer_mut().begin_edit_group();
+
+        for (offset, is_primary) in carets {
+            let at = offset - removed;
+
+            let new = if at == 0 {
+                at
+            } else {
+                self.current_buffer_mut().delete(at - 1, at);
+                removed += 1;
+                at - 1
+            };
+
+            if is_primary {
+                new_primary = new;
+            } else {
+                new_secondary.push(new);
+            }
+        }
+
+        self.current_buffer_mut().end_edit_group();
+
+        new_secondary.dedup();
+        new_secondary.retain(|&offset| offset != new_primary);
+
+        let cursor = self.offset_to_cursor(new_primary);
+        let max_line = self.last_line();
+        let view = self.current_view_mut();
+        view.secondary_cursors = new_secondary;
+        view.cursor = cursor;
+        view.adjust_scroll(max_line);
+        self.clamp_view_cursors(id);
+    }
+
+    /// The forward-delete counterpart of [`Editor::delete_at_cursors`]:
+    /// removes the char under every caret instead of the one before it.
+    /// Carets stay where they are; one sitting at the very end of the
+    /// buffer has nothing under it and deletes nothing.
+    fn delete_forward_at_cursors(&mut self) {
+        let id = self.current_view().buffer_id;
+        let primary = self.cursor_offset();
+
+        let mut carets: Vec<(usize, bool)> = self
+            .current_view()
+            .secondary_cursors
+            .iter()
+            .map(|&offset| (offset, false))
+            .collect();
+        carets.push((primary, true));
+        carets.sort_unstable();
+
+        let mut removed = 0;
+        let mut new_primary = primary;
+        let mut new_secondary = Vec::with_capacity(carets.len() - 1);
+
+        self.current_buffer_mut().begin_edit_group();
+
+        for (offset, is_primary) in carets {
+            let at = offset - removed;
+
+            if at < self.current_buffer().len_chars() {
+                self.current_buffer_mut().delete(at, at + 1);
+                removed += 1;
+            }
+
+            if is_primary {
+                new_primary = at;
+            } else {
+                new_secondary.push(at);
+            }
+        }
+
+        self.current_buffer_mut().end_edit_group();
+
+        new_secondary.dedup();
+        new_secondary.retain(|&offset| offset != new_primary);
+
+        let cursor = self.offset_to_cursor(new_primary);
+        let max_line = self.last_line();
+        let view = self.current_view_mut();
+        view.secondary_cursors = new_secondary;
+        view.cursor = cursor;
+        view.adjust_scroll(max_line);
+        self.clamp_view_cursors(id);
+    }
+
+    /// Secondary caret positions of the current view as `(line, column)`
+    /// pairs, for rendering.
+    pub fn secondary_cursor_positions(&self) -> Vec<(usize, usize)> {
+        let buffer = self.current_buffer();
+
+        self.current_view()
+            .secondary_cursors
+            .iter()
+            .map(|&offset| {
+                let offset = offset.min(buffer.len_chars());
+                let line = buffer.char_to_line(offset);
+                (line, offset - buffer.line_to_char(line))
+            })
+            .collect()
+    }
+
+    /// The most recently killed text in the unnamed register, if any.
+    pub fn register(&self) -> Option<&str> {
+        self.registers.get(&UNNAMED_REGISTER).map(String::as_str)
+    }
+
+    /// Whether typing currently replaces rather than inserts.
+    pub fn overwrite(&self) -> bool {
+        self.overwrite
+    }
+
+    /// Status-line numbers for the current buffer: its 1-based position
+    /// among all open buffers, the total, and how many buffers have
+    /// unsaved changes.
+    pub fn buffer_counts(&self) -> (usize, usize, usize) {
+        let current = self.current_view().buffer_id;
+        let index = self
+            .buffers
+            .iter()
+            .position(|b| b.id() == current)
+            .unwrap_or(0)
+            + 1;
+        let modified = self.buffers.iter().filter(|b| b.is_modified()).count();
+
+        (index, self.buffers.len(), modified)
+    }
+
+    /// The active selection as an ordered char-offset range, if any.
+    pub fn selection_char_range(&self) -> Option<(usize, usize)> {
+        let view = self.current_view();
+        let anchor = view.selection_anchor?;
+        let buffer = self.current_buffer();
+
+        let a = buffer.line_to_char(anchor.0) + anchor.1;
+        let b = buffer.line_to_char(view.cursor.0) + view.cursor.1;
+
+        Some((a.min(b), a.max(b)))
+    }
+
+    /// What a shell pipe operates on: the active selection, or the whole
+    /// buffer when there is none. Returns the buffer id, the char range,
+    /// and the text inside it, so the subprocess can run without any
+    /// lock on the editor.
+    pub fn pipe_region(&self) -> (BufferId, usize, usize, String) {
+        let buffer = self.current_buffer();
+        let (start, end) = self
+            .selection_char_range()
+            .unwrap_or((0, buffer.len_chars()));
+
+        (buffer.id(), start, end, buffer.slice(start, end))
+    }
+
+    /// Replaces chars `start..end` of buffer `id` with `text` as a
+    /// single undo unit, clamping the range (the buffer may have changed
+    /// since it was computed) and every view's cursor afterwards.
+    pub fn replace_region(&mut self, id: BufferId, start: usize, end: usize, text: &str) {
+        if let Some(buffer) = self.buffer_mut(id) {
+            let end = end.min(buffer.len_chars());
+            let start = start.min(end);
+
+            buffer.begin_edit_group();
+            buffer.delete(start, end);
+            buffer.insert(start, text);
+            buffer.end_edit_group();
+        }
+
+        self.clamp_view_cursors(id);
+    }
+
+    /// Moves the cursor to the start of the given zero-indexed line,
+    /// clamped to the buffer.
+    pub fn goto_line(&mut self, line: usize) {
+        let cursor = self.position_to_cursor(line, 0);
+        let max_line = self.last_line();
+        let view = self.current_view_mut();
+        view.cursor = cursor;
+        view.adjust_scroll(max_line);
+    }
+
+    /// Jumps to the next match of `term` at or after char `from` and
+    /// reports its ordinal, e.g. "Match 3 of 17". With no matches the
+    /// cursor stays where it is; a pattern that doesn't compile is an
+    /// error.
+    fn run_search(&mut self, term: &str, kind: SearchKind, from: usize) -> EditorEvent {
+        if kind == SearchKind::Literal {
+            return self.run_literal_search(term, from);
+        }
+
+        let built = match kind {
+            SearchKind::Regex => regex::Regex::new(term),
+            // Case-insensitive search is a literal term run through the
+            // regex engine with its metachars escaped.
+            _ => regex::RegexBuilder::new(&regex::escape(term))
+                .case_insensitive(true)
+                .build(),
+        };
+
+        let regex = match built {
+            Ok(regex) => regex,
+            Err(err) => return EditorEvent::Error(EditorError::InvalidRegex(err.to_string())),
+        };
+
+        let buffer = self.current_buffer();
+        let total = buffer.count_matches_regex(&regex);
+
+        if total == 0 {
+            return EditorEvent::Info("No matches".into());
+        }
+
+        let (found, _end) = buffer
+            .search_regex(&regex, from)
+            .expect("a positive count means some match exists");
+        let ordinal = buffer.regex_matches_before(&regex, found) + 1;
+
+        self.jump_to_match(found);
+        EditorEvent::Info(format!("Match {} of {}", ordinal, total))
+    }
+
+    /// The [`SearchKind::Literal`] arm of [`run_search`](Editor::run_search),
+    /// which counts overlapping matches and needs no compilation step.
+    fn run_literal_search(&mut self, term: &str, from: usize) -> EditorEvent {
+        let buffer = self.current_buffer();
+        let total = buffer.count_matches(term);
+
+        if total == 0 {
+            return EditorEvent::Info("No matches".into());
+        }
+
+        let found = buffer
+            .search(term, from)
+            .expect("a positive count means some match exists");
+        let ordinal = buffer.matches_before(term, found) + 1;
+
+        self.jump_to_match(found);
+        EditorEvent::Info(format!("Match {} of {}", ordinal, total))
+    }
+
+    /// Puts the cursor on the match starting at char `found`.
+    fn jump_to_match(&mut self, found: usize) {
+        let cursor = self.offset_to_cursor(found);
+        let max_line = self.last_line();
+        let view = self.current_view_mut();
+        view.cursor = cursor;
+        view.adjust_scroll(max_line);
+    }
+
+    /// Moves the cursor one step, returning whether it actually moved.
+    /// A move at the buffer boundary makes no progress.
+    fn move_cursor(&mut self, direction: Direction) -> bool {
+        let view = self.current_view();
+        let (line, column) = view.cursor;
+
+        // Vertical moves aim for the sticky column from the start of the
+        // run, so passing through a short line doesn't lose the original
+        // column. The first vertical move records it.
+        let desired = match direction {
+            Direction::Up | Direction::Down => Some(view.desired_column.unwrap_or(column)),
+            Direction::Left | Direction::Right => None,
+        };
+        let goal = desired.unwrap_or(column);
+
+        let buffer = self.current_buffer();
+
+        let cursor = match direction {
+            Direction::Up if line > 0 => (line - 1, goal.min(buffer.line_len(line - 1))),
+            Direction::Down if line + 1 < buffer.len_lines() => {
+                (line + 1, goal.min(buffer.line_len(line + 1)))
+            }
+            Direction::Left if column > 0 => (line, column - 1),
+            Direction::Right if column < buffer.line_len(line) => (line, column + 1),
+            _ => (line, column),
+        };
+
+        let view = self.current_view_mut();
+        view.cursor = cursor;
+        view.desired_column = desired;
+
+        cursor != (line, column)
+    }
+
+    /// Removes the current buffer and every view onto it. Focus moves to
+    /// the view that was adjacent to the current one — the next in order,
+    /// or the previous when the current view was last — rather than
+    /// jumping to the end of the list. Deleting the only buffer leaves a
+    /// fresh scratch buffer so the editor always has something to show.
+    /// Any window split collapses, since the surviving view indices no
+    /// longer line up with what the windows pointed at.
+    pub fn delete_current_buffer(&mut self) {
+        let id = self.current_view().buffer_id;
+        let index = self.current_view;
+
+        // Where the neighbor lands once views onto this buffer are gone.
+        let surviving_before = self.views[..index]
+            .iter()
+            .filter(|v| v.buffer_id != id)
+            .count();
+
+        self.buffers.retain(|b| b.id() != id);
+        self.views.retain(|v| v.buffer_id != id);
+        self.windows.clear();
+        self.focused_window = 0;
+
+        if self.views.is_empty() {
+            let id = self.allocate_buffer_id();
+            self.create_buffer_with_view(Buffer::new(id));
+            self.relayout();
+            return;
+        }
+
+        self.set_current_view(surviving_before.min(self.views.len() - 1));
+        self.relayout();
+    }
+
+    /// Divides the frame between the windows, giving each view an equal
+    /// column share.
+    fn relayout(&mut self) {
+        let (width, height) = self.frame;
+        let pane_width = width / self.windows.len().max(1);
+
+        for view in &mut self.views {
+            view.width = pane_width;
+            view.height = height;
+        }
+    }
+
+    /// Writes swap files for every modified file-backed buffer, ignoring
+    /// individual failures. Run periodically by the server so edits
+    /// survive a crash between saves.
+    pub fn write_swap_files(&self) {
+        for buffer in &self.buffers {
+            if buffer.is_modified() && buffer.filepath.is_some() {
+                let _ = buffer.write_swap();
+            }
+        }
+    }
+
+    /// Saves every modified buffer that has a backing file, ignoring
+    /// individual failures, and returns how many were written. Used for
+    /// best-effort saves on shutdown and by auto-save.
+    pub fn save_modified_buffers(&mut self) -> usize {
+        let mut saved = 0;
+
+        for buffer in &mut self.buffers {
+            if buffer.is_modified() && buffer.filepath.is_some() && buffer.save().is_ok() {
+                saved += 1;
+            }
+        }
+
+        saved
+    }
+
+    /// Applies a single command to the editor, returning the event the
+    /// caller should react to. This is the stable entry point for
+    /// library embedders: it speaks [`EditorInput`] directly, with no
+    /// key handling in between.
+    ///
+    /// ```
+    /// use iota_core::{Editor, EditorInput};
+    ///
+    /// let mut editor = Editor::new();
+    /// editor.apply(EditorInput::Paste("hello".into()));
+    /// assert_eq!(editor.current_buffer().to_string(), "hello");
+    /// ```
+    pub fn apply(&mut self, input: EditorInput) -> EditorEvent {
+        self.execute_command(input)
+    }
+
+    /// Executes a single command against the editor, returning the event
+    /// the frontend should react to. [`Editor::apply`] is the public
+    /// name for this.
+    pub fn execute_command(&mut self, input: EditorInput) -> EditorEvent {
+        // Any command other than a repeated quit cancels a pending quit,
+        // and likewise for a pending buffer close.
+        if !matches!(input, EditorInput::Quit) {
+            self.pending_quit = false;
+        }
+        if !matches!(input, EditorInput::DeleteBuffer) {
+            self.pending_delete = false;
+        }
+
+        // Only an unbroken run of vertical moves keeps the sticky
+        // column; anything else — a horizontal move, an edit, a click —
+        // forgets it.
+        if !matches!(
+            input,
+            EditorInput::MoveCursor(Direction::Up | Direction::Down)
+        ) {
+            self.current_view_mut().desired_column = None;
+        }
+
+        // A register selection consumes the next typed char as the
+        // register name; anything else drops the selection.
+        if self.selecting_register {
+            self.selecting_register = false;
+
+            if let EditorInput::Insert(c) = input {
+                self.active_register = Some(c);
+                return EditorEvent::Info(format!("Register '{}'", c));
+            }
+        }
+
+        if matches!(input, EditorInput::SelectRegister) {
+            self.selecting_register = true;
+            return EditorEvent::Render;
+        }
+
+        // Digits typed while a universal argument is pending accumulate
+        // into the count instead of inserting.
+        if let Some(count) = self.pending_count {
+            if let EditorInput::Insert(c) = input {
+                if let Some(digit) = c.to_digit(10) {
+                    self.pending_count =
+                        Some(count.saturating_mul(10).saturating_add(digit as usize));
+                    return EditorEvent::Render;
+                }
+            }
+        }
+
+        if matches!(input, EditorInput::UniversalArgument) {
+            self.pending_count = Some(0);
+            return EditorEvent::Render;
+        }
+
+        // Remember buffer changes for the repeat command. Typed chars —
+        // newlines included — extend one remembered run; everything
+        // else closes the run, but only another change replaces it.
+        match &input {
+            EditorInput::Insert(c) => self.remember_typed_char(*c),
+            EditorInput::InsertNewline => self.remember_typed_char('\n'),
+            EditorInput::RepeatLast => {}
+            input if remembers_as_change(input) => {
+                self.last_change = Some(input.clone());
+                self.last_change_is_typing = false;
+            }
+            _ => self.last_change_is_typing = false,
+        }
+
+        // Whatever comes next consumes the count, running that many
+        // times. Repetition stops as soon as a run does anything other
+        // than render — an error, a bell at the buffer edge.
+        let count = match self.pending_count.take() {
+            Some(count) => count.max(1),
+            None => 1,
+        };
+
+        let mut event = self.dispatch(input.clone());
+
+        for _ in 1..count {
+            if event != EditorEvent::Render {
+                break;
+            }
+
+            event = self.dispatch(input.clone());
+        }
+
+        event
+    }
+
+    /// Extends the typed-char run the repeat command remembers, or
+    /// starts a fresh one when the last command wasn't typing.
+    fn remember_typed_char(&mut self, c: char) {
+        if self.last_change_is_typing {
+            if let Some(EditorInput::Paste(text)) = &mut self.last_change {
+                text.push(c);
+                return;
+            }
+        }
+
+        self.last_change = Some(EditorInput::Paste(c.to_string()));
+        self.last_change_is_typing = true;
+    }
+
+    /// Runs one command once. [`Editor::execute_command`] layers the
+    /// universal-argument repetition on top of this.
+    fn dispatch(&mut self, input: EditorInput) -> EditorEvent {
+        // Reject edits to read-only buffers up front so the individual
+        // arms don't each need to check.
+        let edits_buffer = matches!(
+            input,
+            EditorInput::Insert(_)
+                | EditorInput::InsertNewline
+                | EditorInput::OpenLineBelow
+                | EditorInput::OpenLineAbove
+                | EditorInput::Paste(_)
+                | EditorInput::InsertFile(_)
+                | EditorInput::DeleteChar
+                | EditorInput::DeleteForward
+                | EditorInput::Undo
+                | EditorInput::Redo
+                | EditorInput::TransposeChars
+                | EditorInput::KillLine
+                | EditorInput::DeleteWordBackward
+                | EditorInput::DeleteWordForward
+                | EditorInput::Yank
+                | EditorInput::SortLines { .. }
+                | EditorInput::NormalizeBlankLines
+                | EditorInput::ToggleComment
+        );
+
+        if edits_buffer && self.current_buffer().is_read_only() {
+            return EditorEvent::Info("Buffer is read-only".into());
+        }
+
+        match input {
+            EditorInput::OpenFile(path) => match self.open_file(&path) {
+                Ok(event) => event,
+                Err(err) => EditorEvent::Error(EditorError::io(path, &err)),
+            },
+            EditorInput::OpenScratch { name, contents } => {
+                self.open_scratch(&name, &contents);
+                EditorEvent::Render
+            }
+            EditorInput::NewBuffer => {
+                let id = self.allocate_buffer_id();
+                self.create_buffer_with_view(Buffer::new(id));
+                EditorEvent::Render
+            }
+            EditorInput::DeleteBuffer => {
+                if self.current_buffer().is_modified() && !self.pending_delete {
+                    self.pending_delete = true;
+                    return EditorEvent::Error(EditorError::UnsavedChanges {
+                        command: "close".into(),
+                    });
+                }
+
+                self.pending_delete = false;
+                self.remember_view_state();
+                self.delete_current_buffer();
+                EditorEvent::Render
+            }
+            EditorInput::RenameFile(path) => {
+                match self.current_buffer_mut().rename(&path, false) {
+                    Ok(()) => EditorEvent::Info(format!("Renamed to {}", path.display())),
+                    Err(err) => EditorEvent::Error(EditorError::RenameFailed(err.to_string())),
+                }
+            }
+            EditorInput::Insert(c) => {
+                self.insert_at_cursors(&c.to_string());
+                EditorEvent::Render
+            }
+            EditorInput::InsertNewline => {
+                self.insert_at_cursors("\n");
+                EditorEvent::Render
+            }
+            EditorInput::OpenLineBelow | EditorInput::OpenLineAbove => {
+                let id = self.current_view().buffer_id;
+                let at = self.current_view().cursor;
+
+                let offset = if matches!(input, EditorInput::OpenLineBelow) {
+                    self.current_buffer_mut().open_line_below(at)
+                } else {
+                    self.current_buffer_mut().open_line_above(at)
+                };
+
+                let cursor = self.offset_to_cursor(offset);
+                let max_line = self.last_line();
+                let view = self.current_view_mut();
+                view.cursor = cursor;
+                view.adjust_scroll(max_line);
+                self.clamp_view_cursors(id);
+                EditorEvent::Render
+            }
+            EditorInput::Paste(text) => {
+                self.insert_at_cursors(&text);
+                EditorEvent::Render
+            }
+            EditorInput::InsertFile(path) => match fs::read_to_string(&path) {
+                Ok(contents) => {
+                    self.insert_at_cursors(&contents);
+                    EditorEvent::Render
+                }
+                Err(err) => EditorEvent::Error(EditorError::io(path, &err)),
+            },
+            EditorInput::DeleteChar => {
+                self.delete_at_cursors();
+                EditorEvent::Render
+            }
+            EditorInput::DeleteForward => {
+                self.delete_forward_at_cursors();
+                EditorEvent::Render
+            }
+            EditorInput::Undo => {
+                let id = self.current_view().buffer_id;
+
+                match self.current_buffer_mut().undo() {
+                    Some(offset) => {
+                        let cursor = self.offset_to_cursor(offset);
+                        let max_line = self.last_line();
+                        let view = self.current_view_mut();
+                        view.cursor = cursor;
+                        view.adjust_scroll(max_line);
+                        self.clamp_view_cursors(id);
+                        EditorEvent::Render
+                    }
+                    None => EditorEvent::Info("Nothing to undo".into()),
+                }
+            }
+            EditorInput::Redo => {
+                let id = self.current_view().buffer_id;
+
+                match self.current_buffer_mut().redo() {
+                    Some(offset) => {
+                        let cursor = self.offset_to_cursor(offset);
+                        let max_line = self.last_line();
+                        let view = self.current_view_mut();
+                        view.cursor = cursor;
+                        view.adjust_scroll(max_line);
+                        self.clamp_view_cursors(id);
+                        EditorEvent::Render
+                    }
+                    None => EditorEvent::Info("Nothing to redo".into()),
+                }
+            }
+            EditorInput::RepeatLast => match self.last_change.clone() {
+                Some(change) => self.dispatch(change),
+                None => EditorEvent::Info("No change to repeat".into()),
+            },
+            EditorInput::AddCursorBelow => {
+                let buffer = self.current_buffer();
+                let view = self.current_view();
+                let column = view.cursor.1;
+
+                // Stack downward from the lowest existing caret.
+                let lowest = view
+                    .secondary_cursors
+                    .iter()
+                    .map(|&offset| buffer.char_to_line(offset))
+                    .max()
+                    .unwrap_or(0)
+                    .max(view.cursor.0);
+
+                let target = lowest + 1;
+                if target >= buffer.len_lines() {
+                    return EditorEvent::Info("No line below".into());
+                }
+
+                let offset = buffer.line_to_char(target) + column.min(buffer.line_len(target));
+                let primary = self.cursor_offset();
+                let view = self.current_view_mut();
+
+                if offset != primary && !view.secondary_cursors.contains(&offset) {
+                    view.secondary_cursors.push(offset);
+                    view.secondary_cursors.sort_unstable();
+                }
+
+                EditorEvent::Render
+            }
+            EditorInput::ClearCursors => {
+                self.current_view_mut().secondary_cursors.clear();
+                EditorEvent::Render
+            }
+            EditorInput::Cancel => {
+                // The pending quit/delete/register/count states were
+                // already dropped by the `execute_command` preamble, as
+                // for any other command; what remains is view state.
+                self.active_register = None;
+
+                let view = self.current_view_mut();
+                view.selection_anchor = None;
+                view.secondary_cursors.clear();
+                EditorEvent::Render
+            }
+            EditorInput::ToggleOverwrite => {
+                self.overwrite = !self.overwrite;
+                EditorEvent::Render
+            }
+            EditorInput::TransposeChars => {
+                let id = self.current_view().buffer_id;
+                let offset = self.cursor_offset();
+                let advanced = self.current_buffer_mut().transpose_chars(offset);
+                let cursor = self.offset_to_cursor(advanced);
+                let max_line = self.last_line();
+                let view = self.current_view_mut();
+                view.cursor = cursor;
+                view.adjust_scroll(max_line);
+                self.clamp_view_cursors(id);
+                EditorEvent::Render
+            }
+            EditorInput::KillLine => {
+                let id = self.current_view().buffer_id;
+                let offset = self.cursor_offset();
+                let (killed, _) = self.current_buffer_mut().kill_to_line_end(offset);
+                let target = self.active_register.take().unwrap_or(UNNAMED_REGISTER);
+
+                if !killed.is_empty() {
+                    self.registers.insert(target, killed);
+                }
+
+                self.clamp_view_cursors(id);
+                EditorEvent::Render
+            }
+            EditorInput::DeleteWordBackward | EditorInput::DeleteWordForward => {
+                let id = self.current_view().buffer_id;
+                let offset = self.cursor_offset();
+
+                let (killed, new_offset) =
+                    if matches!(input, EditorInput::DeleteWordBackward) {
+                        self.current_buffer_mut().delete_word_backward(offset)
+                    } else {
+                        self.current_buffer_mut().delete_word_forward(offset)
+                    };
+                let target = self.active_register.take().unwrap_or(UNNAMED_REGISTER);
+
+                if !killed.is_empty() {
+                    self.registers.insert(target, killed);
+                }
+
+                let cursor = self.offset_to_cursor(new_offset);
+                let max_line = self.last_line();
+                let view = self.current_view_mut();
+                view.cursor = cursor;
+                view.adjust_scroll(max_line);
+                self.clamp_view_cursors(id);
+                EditorEvent::Render
+            }
+            EditorInput::Yank => {
+                let target = self.active_register.take().unwrap_or(UNNAMED_REGISTER);
+                let text = self.registers.get(&target).filter(|t| !t.is_empty()).cloned();
+
+                match text {
+                    Some(text) => {
+                        self.insert_at_cursors(&text);
+                        EditorEvent::Render
+                    }
+                    None => EditorEvent::Info(format!("Register '{}' is empty", target)),
+                }
+            }
+            // Consumed by `execute_command` before dispatch.
+            EditorInput::SelectRegister => EditorEvent::Render,
+            EditorInput::MoveCursor(direction) => {
+                if !self.move_cursor(direction) {
+                    return EditorEvent::Bell;
+                }
+
+                let max_line = self.last_line();
+                self.current_view_mut().adjust_scroll(max_line);
+                EditorEvent::Render
+            }
+            EditorInput::MoveBufferStart => {
+                let max_line = self.last_line();
+                let view = self.current_view_mut();
+                view.cursor = (0, 0);
+                view.adjust_scroll(max_line);
+                EditorEvent::Render
+            }
+            EditorInput::MoveBufferEnd => {
+                let buffer = self.current_buffer();
+                let line = buffer.len_lines().saturating_sub(1);
+                let cursor = (line, buffer.line_len(line));
+
+                let max_line = self.last_line();
+                let view = self.current_view_mut();
+                view.cursor = cursor;
+                view.adjust_scroll(max_line);
+                EditorEvent::Render
+            }
+            EditorInput::MoveParagraphForward | EditorInput::MoveParagraphBackward => {
+                let offset = self.cursor_offset();
+                let buffer = self.current_buffer();
+
+                let target = if matches!(input, EditorInput::MoveParagraphForward) {
+                    buffer.move_paragraph_forward(offset)
+                } else {
+                    buffer.move_paragraph_backward(offset)
+                };
+
+                match target {
+                    Some(target) => {
+                        let cursor = self.offset_to_cursor(target);
+                        let max_line = self.last_line();
+                        let view = self.current_view_mut();
+                        view.cursor = cursor;
+                        view.adjust_scroll(max_line);
+                        EditorEvent::Render
+                    }
+                    None => EditorEvent::Bell,
+                }
+            }
+            EditorInput::SetCursor(line, column) => {
+                let cursor = self.position_to_cursor(line, column);
+                let max_line = self.last_line();
+                let view = self.current_view_mut();
+                view.cursor = cursor;
+                view.selection_anchor = Some(cursor);
+                // Clicking somewhere collapses multi-cursor editing.
+                view.secondary_cursors.clear();
+                view.adjust_scroll(max_line);
+                EditorEvent::Render
+            }
+            EditorInput::ExtendSelection(line, column) => {
+                let cursor = self.position_to_cursor(line, column);
+                let max_line = self.last_line();
+                let view = self.current_view_mut();
+
+                if view.selection_anchor.is_none() {
+                    view.selection_anchor = Some(view.cursor);
+                }
+
+                view.cursor = cursor;
+                view.adjust_scroll(max_line);
+                EditorEvent::Render
+            }
+            EditorInput::Recenter => {
+                let max_line = self.last_line();
+                let view = self.current_view_mut();
+                let target = view.cursor.0.saturating_sub(view.height / 2);
+
+                // Stop at the last useful scroll position rather than
+                // pulling blank rows in below the buffer.
+                let max_scroll = if view.height > 0 {
+                    max_line.saturating_sub(view.height - 1)
+                } else {
+                    max_line
+                };
+                view.set_scroll_line(target.min(max_scroll), max_line);
+                EditorEvent::Render
+            }
+            EditorInput::Scroll(delta) => {
+                let max_line = self.current_buffer().len_lines().saturating_sub(1);
+                let view = self.current_view_mut();
+
+                let target = if delta < 0 {
+                    view.scroll_line.saturating_sub(-delta as usize)
+                } else {
+                    view.scroll_line + delta as usize
+                };
+                view.set_scroll_line(target, max_line);
+
+                // Pure scrolling leaves the cursor alone unless it would
+                // end up outside the viewport, in which case it is pulled
+                // to the nearest visible line.
+                if view.height > 0 {
+                    let top = view.scroll_line;
+                    let bottom = view.scroll_line + view.height - 1;
+                    let line = view.cursor.0.clamp(top, bottom);
+
+                    if line != view.cursor.0 {
+                        let cursor = self.position_to_cursor(line, self.current_view().cursor.1);
+                        self.current_view_mut().cursor = cursor;
+                    }
+                }
+
+                EditorEvent::Render
+            }
+            EditorInput::Resize(width, height) => {
+                self.frame = (width, height);
+                self.relayout();
+                EditorEvent::Render
+            }
+            EditorInput::SplitWindow => {
+                let view = self.current_view();
+                let mut split = View::new(view.buffer_id);
+                split.cursor = view.cursor;
+                split.scroll_line = view.scroll_line;
+                split.scroll_column = view.scroll_column;
+
+                self.views.push(split);
+                self.windows
+                    .insert(self.focused_window + 1, self.views.len() - 1);
+                self.relayout();
+                EditorEvent::Render
+            }
+            EditorInput::FocusOtherWindow => {
+                self.focused_window = (self.focused_window + 1) % self.windows.len();
+                self.current_view = self.windows[self.focused_window];
+                EditorEvent::Render
+            }
+            EditorInput::UnsplitWindow => {
+                let keep = self.windows[self.focused_window];
+                self.windows = vec![keep];
+                self.focused_window = 0;
+                self.relayout();
+                EditorEvent::Render
+            }
+            EditorInput::GotoLine(line) => {
+                self.goto_line(line);
+                EditorEvent::Render
+            }
+            EditorInput::Search { term, kind } => {
+                let from = self.cursor_offset();
+                self.search_term = Some((term.clone(), kind));
+                self.run_search(&term, kind, from)
+            }
+            EditorInput::SearchNext => match self.search_term.clone() {
+                // Step past the current position so repeating advances
+                // instead of refinding the match under the cursor.
+                Some((term, kind)) => {
+                    let from = self.cursor_offset() + 1;
+                    self.run_search(&term, kind, from)
+                }
+                None => EditorEvent::Info("No search term".into()),
+            },
+            EditorInput::CountWords => {
+                let range = self.selection_char_range();
+                let (words, lines, chars, bytes) = self.current_buffer().word_count(range);
+
+                let scope = if range.is_some() { "Selection" } else { "Buffer" };
+                EditorEvent::Info(format!(
+                    "{}: {} words, {} lines, {} chars, {} bytes",
+                    scope, words, lines, chars, bytes
+                ))
+            }
+            EditorInput::ToggleComment => {
+                let id = self.current_view().buffer_id;
+                // Without a selection, toggle just the cursor's line.
+                let range = self.selection_char_range().unwrap_or_else(|| {
+                    let at = self.cursor_offset();
+                    (at, at)
+                });
+                let token = comment_token(self.current_buffer().filepath.as_deref());
+
+                self.current_buffer_mut()
+                    .toggle_line_comment(Some(range), token);
+                self.clamp_view_cursors(id);
+                EditorEvent::Render
+            }
+            EditorInput::SortLines {
+                ignore_case,
+                reverse,
+            } => {
+                let id = self.current_view().buffer_id;
+                let range = self.selection_char_range();
+
+                self.current_buffer_mut()
+                    .sort_lines(range, ignore_case, reverse);
+                self.clamp_view_cursors(id);
+                EditorEvent::Render
+            }
+            EditorInput::NormalizeBlankLines => {
+                let id = self.current_view().buffer_id;
+
+                self.current_buffer_mut().collapse_blank_lines();
+                self.clamp_view_cursors(id);
+                EditorEvent::Render
+            }
+            // Consumed by `execute_command` before dispatch; reaching it
+            // here means a bare repeat of the prefix, which does nothing.
+            EditorInput::UniversalArgument => EditorEvent::Render,
+            // Recorded and replayed by the frontend's key layer; the
+            // core only ever sees the inputs a replay resolves to.
+            EditorInput::StartMacro | EditorInput::EndMacro | EditorInput::PlayMacro => {
+                EditorEvent::Render
+            }
+            EditorInput::EndSelection => {
+                let view = self.current_view_mut();
+
+                if view.selection_anchor == Some(view.cursor) {
+                    view.selection_anchor = None;
+                }
+
+                EditorEvent::Render
+            }
+            EditorInput::WriteRegion(path) => {
+                let range = self.selection_char_range();
+
+                match self.current_buffer().write_range_to(range, &path) {
+                    Ok(()) => EditorEvent::Info(format!("Wrote {}", path.display())),
+                    Err(err) => EditorEvent::Error(EditorError::io(path, &err)),
+                }
+            }
+            EditorInput::Save => {
+                // A pathless buffer is a normal situation, not an IO
+                // failure; point at save-as instead of erroring.
+                if self.current_buffer().filepath.is_none() {
+                    return EditorEvent::Info("No file name; use save-as".into());
+                }
+
+                match self.current_buffer_mut().save() {
+                    Ok(()) => {
+                        self.remember_view_state();
+                        EditorEvent::Info("Saved".into())
+                    }
+                    Err(err) => EditorEvent::Error(EditorError::SaveFailed(err.to_string())),
+                }
+            }
+            EditorInput::Quit => {
+                let any_modified = self.buffers.iter().any(|b| b.is_modified());
+
+                if any_modified && !self.pending_quit {
+                    self.pending_quit = true;
+                    EditorEvent::Error(EditorError::UnsavedChanges {
+                        command: "quit".into(),
+                    })
+                } else {
+                    EditorEvent::Shutdown
+                }
+            }
+            EditorInput::ForceQuit => EditorEvent::Shutdown,
+        }
+    }
+}
+
+/// Whether `input` is the kind of buffer change the repeat command
+/// replays. Typed chars are handled separately so they coalesce, and
+/// undo and redo change the buffer too, but repeating them is never
+/// what `.` means.
+fn remembers_as_change(input: &EditorInput) -> bool {
+    matches!(
+        input,
+        EditorInput::Paste(_)
+            | EditorInput::InsertFile(_)
+            | EditorInput::OpenLineBelow
+            | EditorInput::OpenLineAbove
+            | EditorInput::DeleteChar
+            | EditorInput::DeleteForward
+            | EditorInput::TransposeChars
+            | EditorInput::KillLine
+            | EditorInput::DeleteWordBackward
+            | EditorInput::DeleteWordForward
+            | EditorInput::Yank
+            | EditorInput::SortLines { .. }
+            | EditorInput::NormalizeBlankLines
+            | EditorInput::ToggleComment
+    )
+}
+
+/// The line-comment token for the file at `path`, by extension. `# `
+/// is the fallback for everything unrecognized.
+fn comment_token(path: Option<&Path>) -> &'static str {
+    let extension = path
+        .and_then(|p| p.extension())
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+
+    match extension {
+        "rs" | "c" | "h" | "cpp" | "go" | "js" | "ts" | "java" => "// ",
+        "lisp" | "el" | "scm" => ";; ",
+        "lua" | "sql" => "-- ",
+        _ => "# ",
+    }
+}
+
+/// The canonical spelling of `path`, falling back to the path as given
+/// when it can't be resolved, e.g. the file no longer exists.
+fn canonical_path(path: &Path) -> std::path::PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Whether `buffer` has a swap file modified more recently than `path`,
+/// suggesting a previous session crashed with unsaved changes.
+fn swap_is_newer(buffer: &Buffer, path: &Path) -> bool {
+    let swap = match buffer.swap_path() {
+        Some(swap) => swap,
+        None => return false,
+    };
+
+    match (fs::metadata(&swap), fs::metadata(path)) {
+        (Ok(swap_meta), Ok(file_meta)) => {
+            match (swap_meta.modified(), file_meta.modified()) {
+                (Ok(swap_time), Ok(file_time)) => swap_time > file_time,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+impl Default for Editor {
+    fn default() -> Editor {
+        Editor::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "{}", contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn opening_the_same_file_twice_reuses_the_buffer() {
+        let file = temp_file("hello\n");
+        let mut editor = Editor::new();
+
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        let buffers_after_first = editor.buffers().len();
+
+        let event = editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        assert_eq!(editor.buffers().len(), buffers_after_first);
+        assert_eq!(
+            event,
+            EditorEvent::Info("Switched to already-open file".into())
+        );
+    }
+
+    #[test]
+    fn opening_into_a_pristine_scratch_reuses_the_buffer() {
+        let file = temp_file("hello\n");
+        let mut editor = Editor::new();
+
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        assert_eq!(editor.buffers().len(), 1, "the scratch buffer is reused");
+        assert_eq!(editor.views().len(), 1);
+        assert_eq!(editor.current_buffer().to_string(), "hello\n");
+        assert_eq!(
+            editor.current_buffer().filepath.as_deref(),
+            Some(file.path())
+        );
+        assert!(!editor.current_buffer().is_modified());
+    }
+
+    #[test]
+    fn opening_from_a_used_buffer_still_creates_a_new_one() {
+        let file = temp_file("hello\n");
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Insert('x'));
+
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        assert_eq!(editor.buffers().len(), 2);
+        assert_eq!(editor.current_buffer().to_string(), "hello\n");
+    }
+
+    #[test]
+    fn opening_a_relative_spelling_of_an_open_file_reuses_the_buffer() {
+        let file = temp_file("hello\n");
+        let mut editor = Editor::new();
+
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        let buffers_after_first = editor.buffers().len();
+
+        // A differently-spelled path to the same file.
+        let mut dotted = file.path().parent().unwrap().join(".");
+        dotted.push(file.path().file_name().unwrap());
+        editor.execute_command(EditorInput::OpenFile(dotted));
+
+        assert_eq!(editor.buffers().len(), buffers_after_first);
+    }
+
+    #[test]
+    fn scrolling_moves_the_view_but_not_the_cursor_while_visible() {
+        let mut editor = Editor::new();
+        for _ in 0..20 {
+            editor.execute_command(EditorInput::InsertNewline);
+        }
+        editor.execute_command(EditorInput::Resize(80, 10));
+        editor.execute_command(EditorInput::SetCursor(12, 0));
+
+        // Cursor at 12 with a 10-row window scrolled to line 6 (the
+        // scroll margin keeps three rows of context below it).
+        editor.execute_command(EditorInput::Scroll(3));
+        let view = editor.current_view();
+        assert_eq!(view.scroll_line, 9);
+        assert_eq!(view.cursor.0, 12, "cursor stays put while visible");
+
+        // Scrolling past the cursor drags it along the top of the window.
+        editor.execute_command(EditorInput::Scroll(10));
+        let view = editor.current_view();
+        assert_eq!(view.scroll_line, 19);
+        assert_eq!(view.cursor.0, 19);
+    }
+
+    #[test]
+    fn scrolling_clamps_to_the_buffer() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Resize(80, 10));
+
+        editor.execute_command(EditorInput::Scroll(100));
+        assert_eq!(editor.current_view().scroll_line, 0);
+
+        editor.execute_command(EditorInput::Scroll(-5));
+        assert_eq!(editor.current_view().scroll_line, 0);
+    }
+
+    #[test]
+    fn recenter_puts_the_cursor_line_in_the_middle_of_the_view() {
+        let contents = "line\n".repeat(40);
+        let file = temp_file(&contents);
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        editor.execute_command(EditorInput::Resize(80, 10));
+        editor.execute_command(EditorInput::SetCursor(20, 0));
+
+        editor.execute_command(EditorInput::Recenter);
+        let view = editor.current_view();
+        assert_eq!(view.scroll_line, 15, "line 20 centered in 10 rows");
+        assert_eq!(view.cursor.0, 20, "recentering never moves the cursor");
+
+        // Near the end it clamps to the last useful scroll position.
+        editor.execute_command(EditorInput::SetCursor(40, 0));
+        editor.execute_command(EditorInput::Recenter);
+        assert_eq!(editor.current_view().scroll_line, 31);
+    }
+
+    #[test]
+    fn the_cursor_keeps_a_margin_of_context_while_moving() {
+        let contents = "line\n".repeat(40);
+        let file = temp_file(&contents);
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        editor.execute_command(EditorInput::Resize(80, 10));
+
+        // Walk down the buffer; once scrolling starts, the cursor never
+        // gets within scrolloff rows of either window edge.
+        for _ in 0..30 {
+            editor.execute_command(EditorInput::MoveCursor(Direction::Down));
+            let view = editor.current_view();
+            let (line, _) = view.cursor;
+            if view.scroll_line > 0 {
+                assert!(line >= view.scroll_line + view.scrolloff);
+            }
+            assert!(line + view.scrolloff < view.scroll_line + view.height);
+        }
+
+        // And the same walking back up.
+        for _ in 0..30 {
+            editor.execute_command(EditorInput::MoveCursor(Direction::Up));
+            let view = editor.current_view();
+            let (line, _) = view.cursor;
+            if view.scroll_line > 0 {
+                assert!(line >= view.scroll_line + view.scrolloff);
+            }
+            assert!(line + view.scrolloff < view.scroll_line + view.height);
+        }
+    }
+
+    #[test]
+    fn the_scroll_margin_gives_way_at_the_buffer_ends() {
+        let contents = "line\n".repeat(40);
+        let file = temp_file(&contents);
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        editor.execute_command(EditorInput::Resize(80, 10));
+
+        // At the very top there is nothing to pad with.
+        assert_eq!(editor.current_view().scroll_line, 0);
+        assert_eq!(editor.current_view().cursor, (0, 0));
+
+        // At the very bottom the view stops at the last line rather than
+        // scrolling blank rows in to honor the margin.
+        editor.execute_command(EditorInput::MoveBufferEnd);
+        let view = editor.current_view();
+        assert_eq!(view.cursor.0, 40);
+        assert_eq!(view.scroll_line, 31);
+    }
+
+    #[test]
+    fn embedders_can_open_buffers_and_switch_views() {
+        let mut editor = Editor::new();
+
+        let id = editor.allocate_buffer_id();
+        let opened = editor.open_buffer(Buffer::from_str(id, "embedded"));
+
+        assert_eq!(opened, id);
+        assert_eq!(editor.current_buffer().to_string(), "embedded");
+        assert_eq!(editor.views().len(), 2);
+
+        editor.focus(0);
+        assert_eq!(editor.current_buffer().to_string(), "");
+
+        // Out-of-range focus is ignored.
+        editor.focus(99);
+        assert_eq!(editor.current_buffer().to_string(), "");
+    }
+
+    #[test]
+    fn stale_cursors_in_other_views_are_clamped_after_edits() {
+        let file = temp_file("hello world\n");
+        let mut editor = Editor::new();
+
+        // Two views onto the same buffer.
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        // Park the first view's cursor at the end of the line...
+        editor.focus(1);
+        editor.execute_command(EditorInput::SetCursor(0, 11));
+        editor.execute_command(EditorInput::EndSelection);
+
+        // ...then delete everything through the second view.
+        editor.focus(2);
+        editor.execute_command(EditorInput::SetCursor(1, 0));
+        for _ in 0..12 {
+            editor.execute_command(EditorInput::DeleteChar);
+        }
+
+        // The first view's cursor must have been pulled back inside, so
+        // editing through it doesn't index past the rope.
+        editor.focus(1);
+        assert_eq!(editor.current_view().cursor, (0, 0));
+        editor.execute_command(EditorInput::Insert('x'));
+        assert_eq!(editor.current_buffer().to_string(), "x");
+    }
+
+    #[test]
+    fn buffer_start_and_end_jumps_move_cursor_and_scroll() {
+        let contents = "line\n".repeat(100);
+        let file = temp_file(&contents);
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        editor.execute_command(EditorInput::Resize(80, 10));
+
+        editor.execute_command(EditorInput::MoveBufferEnd);
+        assert_eq!(editor.current_view().cursor, (100, 0));
+        assert!(editor.current_view().scroll_line > 0);
+
+        editor.execute_command(EditorInput::MoveBufferStart);
+        assert_eq!(editor.current_view().cursor, (0, 0));
+        assert_eq!(editor.current_view().scroll_line, 0);
+    }
+
+    #[test]
+    fn buffer_counts_track_totals_and_dirty_buffers() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::NewBuffer);
+        editor.execute_command(EditorInput::NewBuffer);
+        editor.execute_command(EditorInput::NewBuffer);
+        editor.execute_command(EditorInput::NewBuffer);
+
+        // Dirty the second and fourth buffers.
+        editor.focus(1);
+        editor.execute_command(EditorInput::Insert('x'));
+        editor.focus(3);
+        editor.execute_command(EditorInput::Insert('y'));
+
+        editor.focus(2);
+        assert_eq!(editor.buffer_counts(), (3, 5, 2));
+    }
+
+    #[test]
+    fn paste_inserts_a_multi_line_block_in_one_command() {
+        let file = temp_file("ab");
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        editor.execute_command(EditorInput::SetCursor(0, 1));
+
+        editor.execute_command(EditorInput::Paste("1\n    2\n3".into()));
+
+        assert_eq!(editor.current_buffer().to_string(), "a1\n    2\n3b");
+        assert_eq!(editor.current_view().cursor, (2, 1));
+    }
+
+    #[test]
+    fn overwrite_replaces_until_the_line_runs_out() {
+        let file = temp_file("abc\nxyz");
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        editor.execute_command(EditorInput::ToggleOverwrite);
+        editor.execute_command(EditorInput::Insert('1'));
+        editor.execute_command(EditorInput::Insert('2'));
+        assert_eq!(editor.current_buffer().to_string(), "12c\nxyz");
+
+        // At the end of the line, overwrite degrades to insert: the
+        // newline and the next line are untouched.
+        editor.execute_command(EditorInput::Insert('3'));
+        editor.execute_command(EditorInput::Insert('4'));
+        assert_eq!(editor.current_buffer().to_string(), "1234\nxyz");
+
+        // Toggling back restores plain insertion.
+        editor.execute_command(EditorInput::ToggleOverwrite);
+        editor.execute_command(EditorInput::Insert('5'));
+        assert_eq!(editor.current_buffer().to_string(), "12345\nxyz");
+    }
+
+    #[test]
+    fn deleting_the_middle_buffer_focuses_a_neighbor() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::NewBuffer);
+        editor.execute_command(EditorInput::NewBuffer);
+
+        editor.focus(1);
+        editor.execute_command(EditorInput::DeleteBuffer);
+
+        assert_eq!(
+            editor.current_buffer().name,
+            Some("*scratch-3*".into()),
+            "focus lands on the next view, not the end of the list"
+        );
+
+        // Deleting the last view falls back to the previous one.
+        editor.focus(1);
+        editor.execute_command(EditorInput::DeleteBuffer);
+        assert_eq!(editor.current_buffer().name, Some("*scratch-1*".into()));
+    }
+
+    #[test]
+    fn deleting_the_only_buffer_leaves_a_fresh_scratch() {
+        let file = temp_file("hello\n");
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        editor.execute_command(EditorInput::DeleteBuffer);
+
+        assert_eq!(editor.buffers().len(), 1);
+        assert_eq!(editor.current_buffer().to_string(), "");
+        assert!(editor.current_buffer().filepath.is_none());
+    }
+
+    #[test]
+    fn closing_a_modified_buffer_needs_confirmation() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Insert('x'));
+
+        let first = editor.execute_command(EditorInput::DeleteBuffer);
+        assert!(matches!(
+            first,
+            EditorEvent::Error(EditorError::UnsavedChanges { .. })
+        ));
+        assert_eq!(editor.current_buffer().to_string(), "x");
+
+        editor.execute_command(EditorInput::DeleteBuffer);
+        assert_eq!(editor.current_buffer().to_string(), "");
+    }
+
+    #[test]
+    fn splitting_shows_two_windows_and_cycles_focus() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Resize(80, 10));
+
+        editor.execute_command(EditorInput::SplitWindow);
+        assert_eq!(editor.windows().len(), 2);
+        assert_eq!(editor.focused_window(), 0);
+        assert_eq!(editor.current_view().width, 40, "frame splits evenly");
+
+        // Typing lands in the focused pane's view only.
+        editor.execute_command(EditorInput::FocusOtherWindow);
+        assert_eq!(editor.focused_window(), 1);
+        editor.execute_command(EditorInput::Insert('x'));
+        assert_eq!(editor.current_view().cursor, (0, 1));
+
+        editor.execute_command(EditorInput::FocusOtherWindow);
+        assert_eq!(editor.focused_window(), 0);
+        assert_eq!(editor.current_view().cursor, (0, 0));
+
+        editor.execute_command(EditorInput::UnsplitWindow);
+        assert_eq!(editor.windows().len(), 1);
+        assert_eq!(editor.current_view().width, 80);
+    }
+
+    #[test]
+    fn typing_applies_at_every_cursor() {
+        let mut editor = Editor::new();
+        for c in "ab\ncd\nef".chars() {
+            match c {
+                '\n' => editor.execute_command(EditorInput::InsertNewline),
+                c => editor.execute_command(EditorInput::Insert(c)),
+            };
+        }
+        editor.execute_command(EditorInput::SetCursor(0, 1));
+        editor.execute_command(EditorInput::EndSelection);
+
+        editor.execute_command(EditorInput::AddCursorBelow);
+        editor.execute_command(EditorInput::AddCursorBelow);
+        editor.execute_command(EditorInput::Insert('x'));
+
+        assert_eq!(editor.current_buffer().to_string(), "axb\ncxd\nexf");
+        assert_eq!(editor.current_view().cursor, (0, 2));
+        assert_eq!(editor.secondary_cursor_positions(), vec![(1, 2), (2, 2)]);
+
+        // Backspace undoes it at every caret too.
+        editor.execute_command(EditorInput::DeleteChar);
+        assert_eq!(editor.current_buffer().to_string(), "ab\ncd\nef");
+
+        editor.execute_command(EditorInput::ClearCursors);
+        assert!(editor.current_view().secondary_cursors.is_empty());
+    }
+
+    #[test]
+    fn repeat_last_replays_a_typed_run_as_one_block() {
+        let mut editor = Editor::new();
+        for c in "hi ".chars() {
+            editor.execute_command(EditorInput::Insert(c));
+        }
+
+        // Movement closes the run but doesn't forget it.
+        editor.execute_command(EditorInput::MoveCursor(Direction::Left));
+        editor.execute_command(EditorInput::MoveCursor(Direction::Right));
+
+        editor.execute_command(EditorInput::RepeatLast);
+        assert_eq!(editor.current_buffer().to_string(), "hi hi ");
+    }
+
+    #[test]
+    fn repeat_last_repeats_structural_edits() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("one two three".into()));
+
+        editor.execute_command(EditorInput::DeleteWordBackward);
+        assert_eq!(editor.current_buffer().to_string(), "one two ");
+
+        editor.execute_command(EditorInput::RepeatLast);
+        assert_eq!(editor.current_buffer().to_string(), "one ");
+    }
+
+    #[test]
+    fn repeat_with_no_remembered_change_just_says_so() {
+        let mut editor = Editor::new();
+
+        let event = editor.execute_command(EditorInput::RepeatLast);
+        assert!(matches!(event, EditorEvent::Info(_)));
+    }
+
+    #[test]
+    fn open_line_commands_land_the_cursor_on_the_blank_line() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("one\ntwo\n".into()));
+        editor.execute_command(EditorInput::SetCursor(0, 1));
+        editor.execute_command(EditorInput::EndSelection);
+
+        editor.execute_command(EditorInput::OpenLineBelow);
+        assert_eq!(editor.current_buffer().to_string(), "one\n\ntwo\n");
+        assert_eq!(editor.current_view().cursor, (1, 0));
+
+        editor.execute_command(EditorInput::OpenLineAbove);
+        assert_eq!(editor.current_buffer().to_string(), "one\n\n\ntwo\n");
+        assert_eq!(editor.current_view().cursor, (1, 0));
+    }
+
+    #[test]
+    fn delete_forward_at_line_end_joins_the_next_line() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("one\ntwo\n".into()));
+        editor.execute_command(EditorInput::SetCursor(0, 3));
+        editor.execute_command(EditorInput::EndSelection);
+
+        editor.execute_command(EditorInput::DeleteForward);
+
+        assert_eq!(editor.current_buffer().to_string(), "onetwo\n");
+        assert_eq!(editor.current_view().cursor, (0, 3), "cursor stays at the join");
+    }
+
+    #[test]
+    fn backspace_at_line_start_joins_the_previous_line() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("one\ntwo\n".into()));
+        editor.execute_command(EditorInput::SetCursor(1, 0));
+        editor.execute_command(EditorInput::EndSelection);
+
+        editor.execute_command(EditorInput::DeleteChar);
+
+        assert_eq!(editor.current_buffer().to_string(), "onetwo\n");
+        assert_eq!(editor.current_view().cursor, (0, 3), "cursor lands at the join");
+    }
+
+    #[test]
+    fn delete_forward_at_the_end_of_the_buffer_does_nothing() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("abc".into()));
+
+        editor.execute_command(EditorInput::DeleteForward);
+
+        assert_eq!(editor.current_buffer().to_string(), "abc");
+        assert_eq!(editor.current_view().cursor, (0, 3));
+    }
+
+    #[test]
+    fn kill_line_stores_the_killed_text_in_the_register() {
+        let mut editor = Editor::new();
+        for c in "one two".chars() {
+            editor.execute_command(EditorInput::Insert(c));
+        }
+        editor.execute_command(EditorInput::SetCursor(0, 3));
+        editor.execute_command(EditorInput::EndSelection);
+
+        editor.execute_command(EditorInput::KillLine);
+
+        assert_eq!(editor.current_buffer().to_string(), "one");
+        assert_eq!(editor.register(), Some(" two"));
+    }
+
+    #[test]
+    fn read_only_buffers_reject_edits() {
+        let mut editor = Editor::new();
+        editor.current_buffer_mut().set_read_only(true);
+
+        let event = editor.execute_command(EditorInput::Insert('x'));
+
+        assert_eq!(event, EditorEvent::Info("Buffer is read-only".into()));
+        assert_eq!(editor.current_buffer().to_string(), "");
+        assert!(!editor.current_buffer().is_modified());
+    }
+
+    #[test]
+    fn new_buffers_get_distinct_scratch_names() {
+        let mut editor = Editor::new();
+        let first = editor.current_buffer().name.clone();
+
+        editor.execute_command(EditorInput::NewBuffer);
+        let second = editor.current_buffer().name.clone();
+
+        assert_eq!(first, Some("*scratch-1*".into()));
+        assert_eq!(second, Some("*scratch-2*".into()));
+
+        // The name is not a path, so saving still points at save-as.
+        assert!(matches!(
+            editor.execute_command(EditorInput::Save),
+            EditorEvent::Info(_)
+        ));
+    }
+
+    #[test]
+    fn saving_a_pathless_buffer_suggests_save_as() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Insert('x'));
+
+        assert_eq!(
+            editor.execute_command(EditorInput::Save),
+            EditorEvent::Info("No file name; use save-as".into())
+        );
+    }
+
+    #[test]
+    fn vertical_moves_keep_the_sticky_column_through_short_lines() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenScratch {
+            name: "*test*".into(),
+            contents: "a fairly long line\nabc\na long enough line again\n".into(),
+        });
+        editor.execute_command(EditorInput::SetCursor(0, 10));
+
+        editor.execute_command(EditorInput::MoveCursor(Direction::Down));
+        assert_eq!(editor.current_view().cursor, (1, 3), "clamped to the short line");
+
+        editor.execute_command(EditorInput::MoveCursor(Direction::Down));
+        assert_eq!(editor.current_view().cursor, (2, 10), "original column restored");
+
+        // A horizontal move ends the run; the next vertical move aims
+        // for the new column.
+        editor.execute_command(EditorInput::MoveCursor(Direction::Left));
+        editor.execute_command(EditorInput::MoveCursor(Direction::Up));
+        assert_eq!(editor.current_view().cursor, (1, 3));
+        editor.execute_command(EditorInput::MoveCursor(Direction::Up));
+        assert_eq!(editor.current_view().cursor, (0, 9));
+    }
+
+    #[test]
+    fn named_registers_keep_kills_apart_from_the_unnamed_one() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenScratch {
+            name: "*test*".into(),
+            contents: "one\ntwo\n".into(),
+        });
+
+        // Kill "one" into register a.
+        editor.execute_command(EditorInput::SetCursor(0, 0));
+        editor.execute_command(EditorInput::SelectRegister);
+        editor.execute_command(EditorInput::Insert('a'));
+        editor.execute_command(EditorInput::KillLine);
+
+        // A plain kill lands in the unnamed register instead.
+        editor.execute_command(EditorInput::SetCursor(1, 0));
+        editor.execute_command(EditorInput::KillLine);
+        assert_eq!(editor.register(), Some("two"));
+
+        // Yanking from a brings back the first kill untouched.
+        editor.execute_command(EditorInput::SelectRegister);
+        editor.execute_command(EditorInput::Insert('a'));
+        editor.execute_command(EditorInput::Yank);
+        assert_eq!(editor.current_buffer().to_string(), "\none\n");
+    }
+
+    #[test]
+    fn yanking_an_empty_register_reports_instead_of_inserting() {
+        let mut editor = Editor::new();
+
+        editor.execute_command(EditorInput::SelectRegister);
+        editor.execute_command(EditorInput::Insert('q'));
+        let event = editor.execute_command(EditorInput::Yank);
+
+        assert_eq!(event, EditorEvent::Info("Register 'q' is empty".into()));
+        assert_eq!(editor.current_buffer().to_string(), "");
+    }
+
+    #[test]
+    fn a_universal_argument_repeats_the_next_command() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenScratch {
+            name: "*test*".into(),
+            contents: "x\n".repeat(10),
+        });
+
+        editor.execute_command(EditorInput::UniversalArgument);
+        editor.execute_command(EditorInput::Insert('5'));
+        editor.execute_command(EditorInput::MoveCursor(Direction::Down));
+
+        assert_eq!(editor.current_view().cursor.0, 5);
+
+        // Non-movement commands repeat too.
+        editor.execute_command(EditorInput::UniversalArgument);
+        editor.execute_command(EditorInput::Insert('3'));
+        editor.execute_command(EditorInput::Insert('a'));
+        assert!(editor.current_buffer().to_string().contains("aaa"));
+    }
+
+    #[test]
+    fn digits_insert_normally_without_a_pending_argument() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Insert('5'));
+
+        assert_eq!(editor.current_buffer().to_string(), "5");
+    }
+
+    #[test]
+    fn a_bare_universal_argument_runs_the_command_once() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenScratch {
+            name: "*test*".into(),
+            contents: "x\n".repeat(10),
+        });
+
+        editor.execute_command(EditorInput::UniversalArgument);
+        editor.execute_command(EditorInput::MoveCursor(Direction::Down));
+
+        assert_eq!(editor.current_view().cursor.0, 1);
+    }
+
+    #[test]
+    fn undo_history_stays_with_its_buffer_across_switches() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Insert('a'));
+
+        editor.execute_command(EditorInput::NewBuffer);
+        editor.execute_command(EditorInput::Insert('b'));
+
+        // Back to the first buffer; undo must revert its edit only.
+        editor.focus(0);
+        editor.execute_command(EditorInput::Undo);
+        assert_eq!(editor.current_buffer().to_string(), "");
+
+        editor.focus(1);
+        assert_eq!(editor.current_buffer().to_string(), "b", "the other history is untouched");
+    }
+
+    #[test]
+    fn a_multi_cursor_insert_undoes_as_one_unit() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenScratch {
+            name: "*test*".into(),
+            contents: "one\ntwo\n".into(),
+        });
+        editor.execute_command(EditorInput::SetCursor(0, 0));
+        editor.execute_command(EditorInput::AddCursorBelow);
+
+        editor.execute_command(EditorInput::Insert('x'));
+        assert_eq!(editor.current_buffer().to_string(), "xone\nxtwo\n");
+
+        editor.execute_command(EditorInput::Undo);
+        assert_eq!(editor.current_buffer().to_string(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn undo_on_a_pristine_buffer_reports_nothing_to_undo() {
+        let mut editor = Editor::new();
+        assert_eq!(
+            editor.execute_command(EditorInput::Undo),
+            EditorEvent::Info("Nothing to undo".into())
+        );
+    }
+
+    #[test]
+    fn moves_at_the_buffer_boundary_ring_the_bell() {
+        let mut editor = Editor::new();
+
+        assert_eq!(
+            editor.execute_command(EditorInput::MoveCursor(Direction::Up)),
+            EditorEvent::Bell
+        );
+        assert_eq!(
+            editor.execute_command(EditorInput::MoveCursor(Direction::Left)),
+            EditorEvent::Bell
+        );
+
+        editor.execute_command(EditorInput::Insert('x'));
+        assert_eq!(
+            editor.execute_command(EditorInput::MoveCursor(Direction::Left)),
+            EditorEvent::Render
+        );
+    }
+
+    #[test]
+    fn quit_with_unsaved_changes_needs_confirmation() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Insert('x'));
+
+        let first = editor.execute_command(EditorInput::Quit);
+        assert!(matches!(
+            first,
+            EditorEvent::Error(EditorError::UnsavedChanges { .. })
+        ));
+
+        let second = editor.execute_command(EditorInput::Quit);
+        assert_eq!(second, EditorEvent::Shutdown);
+    }
+
+    #[test]
+    fn clean_editor_quits_immediately() {
+        let mut editor = Editor::new();
+        assert_eq!(
+            editor.execute_command(EditorInput::Quit),
+            EditorEvent::Shutdown
+        );
+    }
+
+    #[test]
+    fn intervening_command_cancels_a_pending_quit() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Insert('x'));
+
+        editor.execute_command(EditorInput::Quit);
+        editor.execute_command(EditorInput::MoveCursor(Direction::Left));
+
+        let event = editor.execute_command(EditorInput::Quit);
+        assert!(matches!(
+            event,
+            EditorEvent::Error(EditorError::UnsavedChanges { .. })
+        ));
+    }
+
+    #[test]
+    fn insert_file_drops_the_contents_at_the_cursor_as_one_undo_unit() {
+        let fixture = temp_file("MIDDLE\n");
+        let mut editor = Editor::new();
+
+        editor.execute_command(EditorInput::Paste("start\nend\n".into()));
+        editor.execute_command(EditorInput::SetCursor(1, 0));
+
+        let event = editor.execute_command(EditorInput::InsertFile(fixture.path().to_path_buf()));
+
+        assert_eq!(event, EditorEvent::Render);
+        assert_eq!(editor.current_buffer().to_string(), "start\nMIDDLE\nend\n");
+        // The cursor lands just past the inserted text.
+        assert_eq!(editor.current_view().cursor, (2, 0));
+
+        editor.execute_command(EditorInput::Undo);
+        assert_eq!(editor.current_buffer().to_string(), "start\nend\n");
+    }
+
+    #[test]
+    fn insert_file_reports_unreadable_paths() {
+        let mut editor = Editor::new();
+
+        let event = editor.execute_command(EditorInput::InsertFile(
+            "/nonexistent/iota-insert-test".into(),
+        ));
+
+        assert!(matches!(
+            event,
+            EditorEvent::Error(EditorError::FileNotFound(_))
+        ));
+        assert_eq!(editor.current_buffer().to_string(), "");
+    }
+
+    #[test]
+    fn cancel_drops_the_selection_and_extra_carets_at_once() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("one\ntwo\nthree\n".into()));
+        editor.execute_command(EditorInput::SetCursor(0, 0));
+        editor.execute_command(EditorInput::ExtendSelection(1, 0));
+        editor.execute_command(EditorInput::AddCursorBelow);
+        editor.execute_command(EditorInput::UniversalArgument);
+
+        let event = editor.execute_command(EditorInput::Cancel);
+
+        assert_eq!(event, EditorEvent::Render);
+        assert_eq!(editor.current_view().selection_anchor, None);
+        assert!(editor.current_view().secondary_cursors.is_empty());
+
+        // The prefix argument died with the cancel: a move afterwards
+        // runs once, not zero-or-many times.
+        editor.execute_command(EditorInput::MoveCursor(Direction::Down));
+        assert_eq!(editor.current_view().cursor.0, 2);
+    }
+
+    #[test]
+    fn write_region_extracts_the_selected_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("snippet.txt");
+
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("one\ntwo\nthree\n".into()));
+        editor.execute_command(EditorInput::SetCursor(1, 0));
+        editor.execute_command(EditorInput::ExtendSelection(3, 0));
+
+        let event = editor.execute_command(EditorInput::WriteRegion(target.clone()));
+
+        assert!(matches!(event, EditorEvent::Info(_)));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "two\nthree\n");
+        // The buffer itself is untouched.
+        assert!(editor.current_buffer().is_modified());
+        assert!(editor.current_buffer().filepath.is_none());
+    }
+
+    #[test]
+    fn write_region_without_a_selection_writes_the_whole_buffer() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("whole.txt");
+
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("all of it\n".into()));
+        editor.execute_command(EditorInput::WriteRegion(target.clone()));
+
+        assert_eq!(fs::read_to_string(&target).unwrap(), "all of it\n");
+    }
+
+    #[test]
+    fn searching_cycles_matches_and_reports_the_ordinal() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("one two\none two\none\n".into()));
+        editor.execute_command(EditorInput::MoveBufferStart);
+
+        let event = editor.execute_command(EditorInput::Search {
+            term: "one".into(),
+            kind: SearchKind::Literal,
+        });
+        assert_eq!(event, EditorEvent::Info("Match 1 of 3".into()));
+        assert_eq!(editor.current_view().cursor, (0, 0));
+
+        let event = editor.execute_command(EditorInput::SearchNext);
+        assert_eq!(event, EditorEvent::Info("Match 2 of 3".into()));
+        assert_eq!(editor.current_view().cursor, (1, 0));
+
+        editor.execute_command(EditorInput::SearchNext);
+        // Past the last match the search wraps back to the first.
+        let event = editor.execute_command(EditorInput::SearchNext);
+        assert_eq!(event, EditorEvent::Info("Match 1 of 3".into()));
+        assert_eq!(editor.current_view().cursor, (0, 0));
+    }
+
+    #[test]
+    fn a_search_with_no_matches_leaves_the_cursor_alone() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("nothing here\n".into()));
+        editor.execute_command(EditorInput::SetCursor(0, 4));
+
+        let event = editor.execute_command(EditorInput::Search {
+            term: "absent".into(),
+            kind: SearchKind::Literal,
+        });
+
+        assert_eq!(event, EditorEvent::Info("No matches".into()));
+        assert_eq!(editor.current_view().cursor, (0, 4));
+    }
+
+    #[test]
+    fn regex_and_case_insensitive_searches_find_their_matches() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("alpha\nbeta\ngamma\n".into()));
+        editor.execute_command(EditorInput::MoveBufferStart);
+
+        let event = editor.execute_command(EditorInput::Search {
+            term: r"(?m)^ga\w+".into(),
+            kind: SearchKind::Regex,
+        });
+        assert_eq!(event, EditorEvent::Info("Match 1 of 1".into()));
+        assert_eq!(editor.current_view().cursor, (2, 0));
+
+        let event = editor.execute_command(EditorInput::Search {
+            term: "BETA".into(),
+            kind: SearchKind::CaseInsensitive,
+        });
+        assert_eq!(event, EditorEvent::Info("Match 1 of 1".into()));
+        assert_eq!(editor.current_view().cursor, (1, 0));
+    }
+
+    #[test]
+    fn an_invalid_regex_is_an_error_not_a_panic() {
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::Paste("text\n".into()));
+
+        let event = editor.execute_command(EditorInput::Search {
+            term: "(unclosed".into(),
+            kind: SearchKind::Regex,
+        });
+
+        assert!(matches!(
+            event,
+            EditorEvent::Error(EditorError::InvalidRegex(_))
+        ));
+    }
+
+    #[test]
+    fn closing_a_file_remembers_the_cursor_for_reopening() {
+        let file = temp_file("one\ntwo\nthree\n");
+        let mut editor = Editor::new();
+
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+        editor.execute_command(EditorInput::SetCursor(2, 1));
+        editor.execute_command(EditorInput::DeleteBuffer);
+
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        assert_eq!(editor.current_view().cursor, (2, 1));
+    }
+
+    #[test]
+    fn a_remembered_cursor_is_clamped_when_the_file_shrank() {
+        let file = temp_file("only\n");
+        let mut editor = Editor::new();
+
+        // A cache entry from when the file was much longer.
+        let mut cache = crate::session::ViewStateCache::default();
+        cache.remember(fs::canonicalize(file.path()).unwrap(), (50, 80));
+        editor.restore_view_state(cache);
+
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        assert_eq!(editor.current_view().cursor, (1, 0));
+    }
+
+    #[test]
+    fn the_view_state_cache_evicts_its_oldest_entry_at_the_cap() {
+        use crate::session::{ViewStateCache, VIEW_STATE_CAP};
+        use std::path::PathBuf;
+
+        let mut cache = ViewStateCache::default();
+        for i in 0..VIEW_STATE_CAP + 1 {
+            cache.remember(PathBuf::from(format!("/tmp/file-{}", i)), (i, 0));
+        }
+
+        assert_eq!(cache.entries.len(), VIEW_STATE_CAP);
+        assert_eq!(cache.lookup(Path::new("/tmp/file-0")), None);
+        assert_eq!(
+            cache.lookup(Path::new("/tmp/file-1")),
+            Some((1, 0))
+        );
+    }
+
+    #[test]
+    fn a_saved_session_restores_the_same_files_and_cursors() {
+        let first = temp_file("one\ntwo\nthree\n");
+        let second = temp_file("alpha\nbeta\n");
+
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(first.path().to_path_buf()));
+        editor.execute_command(EditorInput::GotoLine(2));
+        editor.execute_command(EditorInput::OpenFile(second.path().to_path_buf()));
+        editor.execute_command(EditorInput::GotoLine(1));
+
+        let session = editor.save_session();
+        assert_eq!(session.files.len(), 2);
+
+        let mut restored = Editor::new();
+        restored.restore_session(&session);
+
+        let paths: Vec<_> = restored
+            .buffers()
+            .iter()
+            .filter_map(|b| b.filepath.clone())
+            .collect();
+        assert_eq!(paths, vec![first.path().to_path_buf(), second.path().to_path_buf()]);
+        assert_eq!(restored.current_view().cursor, (1, 0));
+    }
+
+    #[test]
+    fn restoring_skips_files_that_no_longer_exist() {
+        let file = temp_file("keep\n");
+
+        let mut editor = Editor::new();
+        editor.execute_command(EditorInput::OpenFile(file.path().to_path_buf()));
+
+        let mut session = editor.save_session();
+        session.files.push(SessionFile {
+            path: "/nonexistent/iota-session-test".into(),
+            cursor: (0, 0),
+            scroll_line: 0,
+        });
+
+        let mut restored = Editor::new();
+        restored.restore_session(&session);
+
+        assert_eq!(restored.buffers().len(), 1);
+        assert_eq!(
+            restored.current_buffer().filepath.as_deref(),
+            Some(file.path())
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use std::path::PathBuf;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// How a search term is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SearchKind {
+    /// The term matches exactly, case-sensitively.
+    Literal,
+    /// The term is a regular expression, with the `regex` crate's
+    /// syntax.
+    Regex,
+    /// The term matches exactly, ignoring case.
+    CaseInsensitive,
+}
+
+/// Commands a frontend can send to the [`Editor`](crate::Editor).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EditorInput {
+    /// Open the file at the given path into a new buffer and view.
+    OpenFile(PathBuf),
+    /// Open a new buffer with the given display name and contents but no
+    /// backing file, e.g. piped stdin.
+    OpenScratch { name: String, contents: String },
+    /// Open an empty scratch buffer; it gets an auto-assigned
+    /// `*scratch-N*` name.
+    NewBuffer,
+    /// Close the current buffer and every view onto it, focusing an
+    /// adjacent view.
+    DeleteBuffer,
+    /// Rename the current buffer's backing file on disk and point the
+    /// buffer at the new path. Refuses to clobber an existing file.
+    RenameFile(PathBuf),
+    /// Insert a char at the cursor.
+    Insert(char),
+    /// Insert a line break at the cursor.
+    InsertNewline,
+    /// Open a blank line below the cursor's line and move there, as
+    /// Vim's `o` does. The cursor's column doesn't matter and the line's
+    /// text is left intact, where [`EditorInput::InsertNewline`] would
+    /// split it.
+    OpenLineBelow,
+    /// Open a blank line above the cursor's line and move there, as
+    /// Vim's `O` does.
+    OpenLineAbove,
+    /// Insert a whole block of text at the cursor in one operation, e.g.
+    /// a bracketed paste from the terminal.
+    Paste(String),
+    /// Read the file at the given path and insert its contents at the
+    /// cursor, as one undo unit. Unlike [`EditorInput::OpenFile`] this
+    /// never creates a buffer; the text lands in the current one.
+    InsertFile(PathBuf),
+    /// Delete the char before the cursor. At the start of a line that
+    /// char is the previous line's newline, so this joins the two lines.
+    DeleteChar,
+    /// Delete the char under the cursor, as the Delete key does. At the
+    /// end of a line this joins the next line into the current one; at
+    /// the very end of the buffer it does nothing.
+    DeleteForward,
+    /// Revert the most recent undo unit of the current buffer.
+    Undo,
+    /// Re-apply the most recently undone unit.
+    Redo,
+    /// Re-apply the last buffer-changing command at the current cursor,
+    /// as Vim's `.` does. A run of typed chars replays as one block;
+    /// movement in between doesn't forget the change.
+    RepeatLast,
+    /// Swap the chars around the cursor, as Emacs `C-t` does.
+    TransposeChars,
+    /// Delete from the cursor to the end of the line into the kill
+    /// register, as Emacs `C-k` does.
+    KillLine,
+    /// Delete the word before the cursor into the kill register, as
+    /// Emacs `M-backspace` does.
+    DeleteWordBackward,
+    /// Delete the word at or after the cursor into the kill register,
+    /// as Emacs `M-d` does.
+    DeleteWordForward,
+    /// Insert the contents of the kill register at the cursor, as Emacs
+    /// `C-y` does.
+    Yank,
+    /// The next typed char names the register the following kill or
+    /// yank goes through, instead of the unnamed one.
+    SelectRegister,
+    /// Spawn an extra caret on the line below the lowest caret, at the
+    /// primary cursor's column.
+    AddCursorBelow,
+    /// Collapse back to the single primary cursor.
+    ClearCursors,
+    /// Drop every transient state at once — selection anchor, extra
+    /// carets, pending register, prefix argument — the way Escape
+    /// should. Frontends also use it to abandon an in-flight key chord.
+    Cancel,
+    /// Toggle overwrite mode, where typing replaces the char under the
+    /// cursor instead of inserting before it.
+    ToggleOverwrite,
+    /// Show a second window beside the current one, onto the same view's
+    /// buffer.
+    SplitWindow,
+    /// Move focus to the next window, wrapping around.
+    FocusOtherWindow,
+    /// Collapse the split back to just the focused window.
+    UnsplitWindow,
+    MoveCursor(Direction),
+    /// Jump to the very start of the buffer, as Emacs `M-<` does.
+    MoveBufferStart,
+    /// Jump past the last char of the buffer, as Emacs `M->` does.
+    MoveBufferEnd,
+    /// Jump to the next paragraph boundary — the blank line after the
+    /// current paragraph, or the end of the buffer — as Vim's `}` does.
+    MoveParagraphForward,
+    /// Jump to the previous paragraph boundary, as Vim's `{` does.
+    MoveParagraphBackward,
+    /// Move the cursor to an absolute `(line, column)`, clamping to the
+    /// buffer's bounds, and start a selection there. Used for mouse
+    /// positioning.
+    SetCursor(usize, usize),
+    /// Move the cursor while keeping the selection anchor in place,
+    /// growing the selection. Used for mouse drags.
+    ExtendSelection(usize, usize),
+    /// Finish a selection gesture; an empty selection is dropped.
+    EndSelection,
+    /// Scroll the view by a number of lines (negative is up) without
+    /// moving the cursor, except to keep it inside the viewport.
+    Scroll(i32),
+    /// Scroll so the cursor's line sits in the vertical middle of the
+    /// view, as Emacs `C-l` does, without moving the cursor.
+    Recenter,
+    /// The frontend's text area changed size to `(columns, rows)`.
+    Resize(usize, usize),
+    /// Jump to the start of a zero-indexed line, clamping past-the-end
+    /// targets to the last line.
+    GotoLine(usize),
+    /// Set the active search term and jump to its first match at or
+    /// after the cursor, wrapping past the end of the buffer. With no
+    /// match the cursor stays put.
+    Search { term: String, kind: SearchKind },
+    /// Jump to the next match of the active search term.
+    SearchNext,
+    /// Report word/line/char/byte counts for the selection, or the whole
+    /// buffer without one.
+    CountWords,
+    /// Sort the lines covered by the selection — or the whole buffer
+    /// without one — lexicographically, as one undo unit.
+    SortLines { ignore_case: bool, reverse: bool },
+    /// Collapse every run of two or more blank lines into one and end
+    /// the buffer with exactly one newline, as one undo unit.
+    NormalizeBlankLines,
+    /// Comment out the lines covered by the selection — or the cursor's
+    /// line without one — with the comment token for the buffer's file
+    /// type, or uncomment them if they all already are.
+    ToggleComment,
+    /// Start a numeric argument: digits typed next accumulate a count
+    /// that the following command runs with, as Emacs `C-u` does.
+    UniversalArgument,
+    /// Start recording a keyboard macro. Macros are a key-layer feature:
+    /// the frontend captures raw keys and replays them through its
+    /// keymap, so these three commands are no-ops in the editor core.
+    StartMacro,
+    /// Stop recording the keyboard macro.
+    EndMacro,
+    /// Replay the recorded keyboard macro.
+    PlayMacro,
+    /// Write the current selection — or the whole buffer without one —
+    /// to the given file, leaving the buffer's own path and modified
+    /// flag alone, as Emacs `write-region` does.
+    WriteRegion(PathBuf),
+    /// Save the current buffer to its file.
+    Save,
+    Quit,
+    /// Quit without asking about unsaved changes.
+    ForceQuit,
+}
+
+/// A command failure, broken out by kind so embedders can match on it
+/// instead of parsing message text. The `Display` impl renders the
+/// message frontends show, so the wire protocol can still flatten an
+/// error to a string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EditorError {
+    /// The named file does not exist.
+    FileNotFound(PathBuf),
+    /// Any other IO failure on `path`; `message` is the OS error text.
+    Io { path: PathBuf, message: String },
+    /// Renaming the buffer's backing file failed.
+    RenameFailed(String),
+    /// Writing the buffer to its file failed.
+    SaveFailed(String),
+    /// A search pattern did not compile as a regular expression.
+    InvalidRegex(String),
+    /// The file has a newer swap file, so a previous session may hold
+    /// unsaved changes.
+    StaleSwap(PathBuf),
+    /// A modified buffer is about to be discarded; repeating the named
+    /// command confirms it.
+    UnsavedChanges { command: String },
+}
+
+impl EditorError {
+    /// Wraps an IO failure on `path`, splitting out the common
+    /// not-found case into its own variant.
+    pub fn io(path: PathBuf, err: &std::io::Error) -> EditorError {
+        if err.kind() == std::io::ErrorKind::NotFound {
+            EditorError::FileNotFound(path)
+        } else {
+            EditorError::Io {
+                path,
+                message: err.to_string(),
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for EditorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditorError::FileNotFound(path) => write!(f, "{}: file not found", path.display()),
+            EditorError::Io { path, message } => write!(f, "{}: {}", path.display(), message),
+            EditorError::RenameFailed(message) => write!(f, "Rename failed: {}", message),
+            EditorError::SaveFailed(message) => write!(f, "Save failed: {}", message),
+            EditorError::InvalidRegex(message) => write!(f, "Invalid regex: {}", message),
+            EditorError::StaleSwap(path) => write!(
+                f,
+                "{} has a newer swap file; a previous session may have unsaved changes",
+                path.display()
+            ),
+            EditorError::UnsavedChanges { command } => {
+                write!(f, "Unsaved changes; {} again to discard them", command)
+            }
+        }
+    }
+}
+
+/// What happened as a result of an [`EditorInput`]. Frontends use this to
+/// decide whether to redraw, show a message, or exit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EditorEvent {
+    /// Editor state changed; the frontend should redraw.
+    Render,
+    /// A movement command made no progress, e.g. up on the first line.
+    /// Frontends give some minimal feedback, like ringing the bell.
+    Bell,
+    Info(String),
+    Error(EditorError),
+    Shutdown,
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_round_trip_through_serde() {
+        let inputs = vec![
+            EditorInput::Insert('é'),
+            EditorInput::MoveCursor(Direction::Down),
+            EditorInput::Paste("hi\n".into()),
+            EditorInput::OpenFile(PathBuf::from("/tmp/notes.txt")),
+            EditorInput::Resize(80, 24),
+        ];
+
+        for input in inputs {
+            let json = serde_json::to_string(&input).unwrap();
+            assert_eq!(serde_json::from_str::<EditorInput>(&json).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn events_round_trip_through_serde() {
+        let events = vec![
+            EditorEvent::Render,
+            EditorEvent::Info("Saved".into()),
+            EditorEvent::Error(EditorError::FileNotFound(PathBuf::from("/tmp/gone"))),
+            EditorEvent::Shutdown,
+        ];
+
+        for event in events {
+            let json = serde_json::to_string(&event).unwrap();
+            assert_eq!(serde_json::from_str::<EditorEvent>(&json).unwrap(), event);
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! Core editing state for iota: buffers, views, and the command loop that
+//! drives them. This crate is UI-agnostic; frontends translate their events
+//! into [`EditorInput`] and react to the [`EditorEvent`]s that come back.
+
+pub mod buffer;
+pub mod editor;
+pub mod input;
+pub mod location;
+pub mod session;
+pub mod view;
+
+pub use buffer::{Buffer, BufferId};
+pub use editor::Editor;
+pub use input::{Direction, EditorError, EditorEvent, EditorInput, SearchKind};
+pub use location::{Position, Range};
+pub use session::{Session, SessionFile, ViewStateCache};
+pub use view::View;
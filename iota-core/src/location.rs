@@ -0,0 +1,125 @@
+//! Positions and ranges in a buffer, in zero-indexed `(line, column)`
+//! terms. Ordering is line-major, so positions compare the way they read.
+
+/// A zero-indexed spot in a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    /// The position as the one-indexed `(line, column)` people read in
+    /// a status line. The `+ 1` lives here and only here; everything
+    /// internal stays zero-indexed.
+    pub fn display(self) -> (usize, usize) {
+        (self.line + 1, self.column + 1)
+    }
+}
+
+impl From<(usize, usize)> for Position {
+    fn from((line, column): (usize, usize)) -> Position {
+        Position { line, column }
+    }
+}
+
+impl From<Position> for (usize, usize) {
+    fn from(position: Position) -> (usize, usize) {
+        (position.line, position.column)
+    }
+}
+
+/// A span between two positions, half-open: `start` is inside the range,
+/// `end` is not. Selections and future delete-range/search features
+/// compare and normalize these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn new(start: Position, end: Position) -> Range {
+        Range { start, end }
+    }
+
+    /// The same range with `start <= end`, regardless of the direction it
+    /// was made in (e.g. a selection dragged upward).
+    pub fn normalized(self) -> Range {
+        if self.start <= self.end {
+            self
+        } else {
+            Range {
+                start: self.end,
+                end: self.start,
+            }
+        }
+    }
+
+    /// Whether `position` falls inside the normalized range. The start is
+    /// included, the end is not.
+    pub fn contains(self, position: Position) -> bool {
+        let range = self.normalized();
+        range.start <= position && position < range.end
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_order_line_major() {
+        assert!(Position::new(0, 9) < Position::new(1, 0));
+        assert!(Position::new(2, 3) < Position::new(2, 4));
+        assert!(Position::new(3, 0) > Position::new(2, 99));
+    }
+
+    #[test]
+    fn display_is_one_indexed() {
+        assert_eq!(Position::new(0, 0).display(), (1, 1));
+        assert_eq!(Position::new(41, 7).display(), (42, 8));
+    }
+
+    #[test]
+    fn normalized_swaps_backwards_ranges() {
+        let backwards = Range::new(Position::new(5, 2), Position::new(1, 7));
+        let range = backwards.normalized();
+
+        assert_eq!(range.start, Position::new(1, 7));
+        assert_eq!(range.end, Position::new(5, 2));
+
+        // Already-ordered ranges are untouched.
+        assert_eq!(range.normalized(), range);
+    }
+
+    #[test]
+    fn contains_includes_the_start_but_not_the_end() {
+        let range = Range::new(Position::new(1, 2), Position::new(3, 4));
+
+        assert!(range.contains(Position::new(1, 2)));
+        assert!(range.contains(Position::new(2, 0)));
+        assert!(!range.contains(Position::new(3, 4)));
+        assert!(!range.contains(Position::new(1, 1)));
+
+        // Direction doesn't matter.
+        let backwards = Range::new(Position::new(3, 4), Position::new(1, 2));
+        assert!(backwards.contains(Position::new(2, 0)));
+    }
+
+    #[test]
+    fn empty_ranges_contain_nothing() {
+        let range = Range::new(Position::new(1, 1), Position::new(1, 1));
+
+        assert!(range.is_empty());
+        assert!(!range.contains(Position::new(1, 1)));
+    }
+}
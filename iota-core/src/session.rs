@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A restorable snapshot of the file-backed buffers in a session and
+/// where their cursors were. Built by
+/// [`Editor::save_session`](crate::Editor::save_session); scratch
+/// buffers with no backing file are not part of it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Session {
+    pub files: Vec<SessionFile>,
+}
+
+/// One open file in a [`Session`]: its path and the view position to
+/// come back to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SessionFile {
+    pub path: PathBuf,
+    /// Zero-indexed `(line, column)` cursor position.
+    pub cursor: (usize, usize),
+    /// First buffer line that was visible.
+    pub scroll_line: usize,
+}
+
+/// Most files [`ViewStateCache`] remembers before evicting the oldest.
+pub const VIEW_STATE_CAP: usize = 200;
+
+/// Remembers where the cursor was in files that have since been closed,
+/// so reopening one comes back to the same place. Unlike a [`Session`],
+/// which snapshots what is open right now, this outlives the buffers it
+/// describes. Entries sit in least-recently-updated order and the cache
+/// is capped at [`VIEW_STATE_CAP`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ViewStateCache {
+    pub entries: Vec<ViewStateEntry>,
+}
+
+/// One remembered file position. Paths are stored canonicalized so the
+/// same file reached through different spellings shares an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ViewStateEntry {
+    pub path: PathBuf,
+    /// Zero-indexed `(line, column)` cursor position.
+    pub cursor: (usize, usize),
+}
+
+impl ViewStateCache {
+    /// Records `cursor` for `path`, replacing any earlier entry for the
+    /// same file and evicting the oldest entry once the cap is reached.
+    pub fn remember(&mut self, path: PathBuf, cursor: (usize, usize)) {
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.push(ViewStateEntry { path, cursor });
+
+        if self.entries.len() > VIEW_STATE_CAP {
+            self.entries.remove(0);
+        }
+    }
+
+    /// The remembered cursor for `path`, if any. Callers clamp it to the
+    /// file's current contents; the file may have shrunk since.
+    pub fn lookup(&self, path: &Path) -> Option<(usize, usize)> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .map(|entry| entry.cursor)
+    }
+}
@@ -0,0 +1,93 @@
+use crate::buffer::BufferId;
+
+/// Lines of context [`View::adjust_scroll`] keeps visible above and
+/// below the cursor by default.
+pub const DEFAULT_SCROLLOFF: usize = 3;
+
+/// A window onto a [`Buffer`](crate::Buffer). Several views may share one
+/// buffer, each with its own cursor and scroll position.
+pub struct View {
+    pub buffer_id: BufferId,
+    /// Zero-indexed `(line, column)` cursor position in the buffer.
+    pub cursor: (usize, usize),
+    /// First buffer line visible in this view.
+    pub scroll_line: usize,
+    /// First column visible in this view, for horizontal scrolling.
+    pub scroll_column: usize,
+    /// The column a run of vertical moves is aiming for. Set on the
+    /// first up/down move and kept while the cursor passes through
+    /// shorter lines, so reaching a long enough line restores the
+    /// original column. Cleared by any other command.
+    pub desired_column: Option<usize>,
+    /// Where the active selection started, if one exists. The selection
+    /// covers the span between the anchor and the cursor, in either
+    /// direction.
+    pub selection_anchor: Option<(usize, usize)>,
+    /// Extra caret positions for multi-cursor editing, as char offsets
+    /// into the buffer. Kept sorted ascending and distinct from each
+    /// other and the primary cursor; edits are applied in ascending
+    /// offset order with a running delta so earlier edits never
+    /// invalidate later offsets. Empty in ordinary single-cursor use.
+    pub secondary_cursors: Vec<usize>,
+    /// How many lines of context to keep visible above and below the
+    /// cursor when scrolling, where the buffer allows it.
+    pub scrolloff: usize,
+    /// Visible size of the view as last reported by the frontend, in
+    /// `(columns, rows)` of text area. Zero until a frontend reports it.
+    pub height: usize,
+    pub width: usize,
+}
+
+impl View {
+    pub fn new(buffer_id: BufferId) -> View {
+        View {
+            buffer_id,
+            cursor: (0, 0),
+            scroll_line: 0,
+            scroll_column: 0,
+            desired_column: None,
+            selection_anchor: None,
+            secondary_cursors: Vec::new(),
+            scrolloff: DEFAULT_SCROLLOFF,
+            height: 0,
+            width: 0,
+        }
+    }
+
+    /// Scrolls just far enough that the cursor is inside the visible
+    /// window with [`View::scrolloff`] lines of context above and below
+    /// it. Called after commands that move the cursor; `max_line` is
+    /// the buffer's last line, so the margin never scrolls blank space
+    /// into view at the bottom.
+    pub fn adjust_scroll(&mut self, max_line: usize) {
+        let (line, column) = self.cursor;
+        let margin = self.scrolloff;
+
+        if line < self.scroll_line + margin {
+            // Clamps itself at the top of the buffer.
+            self.scroll_line = line.saturating_sub(margin);
+        } else if self.height > 0 && line + margin >= self.scroll_line + self.height {
+            self.scroll_line = line + margin + 1 - self.height;
+        }
+
+        // At the bottom of the buffer the margin gives way rather than
+        // showing rows past the last line.
+        if self.height > 0 {
+            self.scroll_line = self
+                .scroll_line
+                .min(max_line.saturating_sub(self.height - 1));
+        }
+
+        if column < self.scroll_column {
+            self.scroll_column = column;
+        } else if self.width > 0 && column >= self.scroll_column + self.width {
+            self.scroll_column = column - self.width + 1;
+        }
+    }
+
+    /// Scrolls the view to `line` without touching the cursor, clamped to
+    /// `max_line`.
+    pub fn set_scroll_line(&mut self, line: usize, max_line: usize) {
+        self.scroll_line = line.min(max_line);
+    }
+}
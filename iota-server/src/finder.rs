@@ -0,0 +1,156 @@
+//! The file finder behind the client's quick-open picker: a bounded walk
+//! of the working tree plus the fuzzy matcher clients use to filter it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How deep [`list_files`] descends below its root.
+pub const MAX_DEPTH: usize = 8;
+
+/// Upper bound on the number of files one listing returns, so a walk
+/// started in `/` or a giant monorepo stays cheap and the reply stays
+/// well under the protocol's frame limit.
+pub const MAX_FILES: usize = 10_000;
+
+/// Directories the walk never enters: version control internals and
+/// build output, which are never what the user is looking for.
+const SKIPPED_DIRS: &[&str] = &[".git", "target"];
+
+/// Scores `candidate` against `query` as a case-insensitive subsequence
+/// match. `None` means the query's chars don't all appear in order;
+/// otherwise higher is better. Consecutive matches and matches at the
+/// start of a path component or word score up, and shorter candidates
+/// edge out longer ones that match the same way. An empty query matches
+/// everything.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<i64> {
+    let mut remaining = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0i64;
+    let mut run = 0i64;
+    let mut prev: Option<char> = None;
+
+    for c in candidate.chars() {
+        match remaining.peek() {
+            Some(&q) if c.to_lowercase().next() == Some(q) => {
+                remaining.next();
+
+                // A run of consecutive matches is worth more than the
+                // same chars scattered about.
+                run += 1;
+                score += run;
+
+                // Matching the first char after a separator means the
+                // query lines up with how the path reads.
+                if matches!(prev, None | Some('/' | '_' | '-' | '.' | ' ')) {
+                    score += 10;
+                }
+            }
+            _ => run = 0,
+        }
+
+        prev = Some(c);
+    }
+
+    if remaining.next().is_some() {
+        return None;
+    }
+
+    Some(score - candidate.chars().count() as i64 / 4)
+}
+
+/// Every file under `root`, as paths relative to it, sorted. The walk
+/// skips [`SKIPPED_DIRS`], goes at most [`MAX_DEPTH`] levels down, and
+/// stops collecting at [`MAX_FILES`]; unreadable directories are
+/// silently passed over.
+pub fn list_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut stack = vec![(root.to_path_buf(), 0usize)];
+
+    'walk: while let Some((dir, depth)) = stack.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+
+            if file_type.is_dir() {
+                let name = entry.file_name();
+                if depth + 1 < MAX_DEPTH && !SKIPPED_DIRS.iter().any(|&skip| name == skip) {
+                    stack.push((entry.path(), depth + 1));
+                }
+            } else if file_type.is_file() {
+                if files.len() >= MAX_FILES {
+                    break 'walk;
+                }
+
+                let path = entry.path();
+                files.push(
+                    path.strip_prefix(root)
+                        .map(Path::to_path_buf)
+                        .unwrap_or(path),
+                );
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_requires_the_query_chars_in_order() {
+        assert!(fuzzy_match("edr", "src/editor.rs").is_some());
+        assert!(fuzzy_match("rde", "src/editor.rs").is_none());
+        assert!(fuzzy_match("xyz", "src/editor.rs").is_none());
+
+        // Case-insensitive in both directions.
+        assert!(fuzzy_match("READ", "readme.md").is_some());
+        assert!(fuzzy_match("read", "README.md").is_some());
+    }
+
+    #[test]
+    fn boundary_and_consecutive_matches_rank_higher() {
+        // "ed" opens a path component in editor.rs but is buried mid-word
+        // in README.
+        assert!(fuzzy_match("ed", "src/editor.rs") > fuzzy_match("ed", "README.md"));
+
+        // The same match shape prefers the shorter candidate.
+        assert!(fuzzy_match("view", "view.rs") > fuzzy_match("view", "deeply/nested/view.rs"));
+    }
+
+    #[test]
+    fn the_empty_query_matches_everything() {
+        assert!(fuzzy_match("", "anything").is_some());
+        assert!(fuzzy_match("", "").is_some());
+    }
+
+    #[test]
+    fn the_walk_skips_ignored_directories_and_relativizes_paths() {
+        let root = std::env::temp_dir().join(format!("iota-finder-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join(".git/objects")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("Cargo.toml"), "").unwrap();
+        fs::write(root.join(".git/objects/abc"), "").unwrap();
+        fs::write(root.join("target/debug/binary"), "").unwrap();
+
+        let files = list_files(&root);
+        assert_eq!(
+            files,
+            vec![PathBuf::from("Cargo.toml"), PathBuf::from("src/main.rs")]
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,127 @@
+//! Rudimentary syntax highlighting. The server picks a [`Highlighter`]
+//! per buffer from its file extension and ships the resulting style
+//! runs in [`RenderData`](crate::protocol::RenderData); frontends only
+//! ever see `(start, end, color)` tuples, never a concrete style.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::protocol::HighlightColor;
+
+/// Computes highlight spans for one line at a time. Ranges are half-open
+/// char ranges into `line`; overlapping ranges are resolved in favor of
+/// the earlier one.
+pub trait Highlighter: Send + Sync {
+    fn spans(&self, line: &str) -> Vec<(Range<usize>, HighlightColor)>;
+}
+
+/// Maps file extensions to the highlighter used for buffers backed by
+/// files with that extension.
+#[derive(Default)]
+pub struct HighlighterRegistry {
+    by_extension: HashMap<String, Box<dyn Highlighter>>,
+}
+
+impl HighlighterRegistry {
+    pub fn new() -> HighlighterRegistry {
+        HighlighterRegistry::default()
+    }
+
+    /// The registry with the built-in highlighters: [`BasicCode`] for
+    /// the common `//`-commented languages.
+    pub fn with_defaults() -> HighlighterRegistry {
+        let mut registry = HighlighterRegistry::new();
+
+        for ext in ["rs", "c", "h", "cpp", "go", "js", "ts", "java"] {
+            registry.register(ext, Box::new(BasicCode));
+        }
+
+        registry
+    }
+
+    pub fn register(&mut self, extension: &str, highlighter: Box<dyn Highlighter>) {
+        self.by_extension.insert(extension.to_string(), highlighter);
+    }
+
+    /// The highlighter for the file at `path`, picked by extension.
+    pub fn for_path(&self, path: &Path) -> Option<&dyn Highlighter> {
+        let extension = path.extension()?.to_str()?;
+        self.by_extension.get(extension).map(|h| h.as_ref())
+    }
+}
+
+/// The shared registry render code consults. Built once, on first use.
+pub(crate) fn registry() -> &'static HighlighterRegistry {
+    static REGISTRY: OnceLock<HighlighterRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(HighlighterRegistry::with_defaults)
+}
+
+/// A proof-of-concept highlighter for `//`-commented languages: the
+/// comment itself plus standalone number literals. Real grammars would
+/// be separate [`Highlighter`] impls registered per language.
+pub struct BasicCode;
+
+impl Highlighter for BasicCode {
+    fn spans(&self, line: &str) -> Vec<(Range<usize>, HighlightColor)> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut spans = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i] == '/' && chars.get(i + 1) == Some(&'/') {
+                spans.push((i..chars.len(), HighlightColor::Comment));
+                break;
+            }
+
+            // A run of digits counts as a number only when it doesn't
+            // continue an identifier like `base64`.
+            let boundary = i == 0 || (!chars[i - 1].is_alphanumeric() && chars[i - 1] != '_');
+
+            if boundary && chars[i].is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                spans.push((start..i, HighlightColor::Number));
+            } else {
+                i += 1;
+            }
+        }
+
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_and_numbers_get_spans() {
+        let spans = BasicCode.spans("let x = 42; // the answer");
+
+        assert_eq!(
+            spans,
+            vec![
+                (8..10, HighlightColor::Number),
+                (12..25, HighlightColor::Comment),
+            ]
+        );
+    }
+
+    #[test]
+    fn digits_inside_identifiers_are_not_numbers() {
+        assert_eq!(BasicCode.spans("base64::encode"), vec![]);
+    }
+
+    #[test]
+    fn the_registry_picks_by_extension() {
+        let registry = HighlighterRegistry::with_defaults();
+
+        assert!(registry.for_path(Path::new("src/main.rs")).is_some());
+        assert!(registry.for_path(Path::new("notes.txt")).is_none());
+        assert!(registry.for_path(Path::new("Makefile")).is_none());
+    }
+}
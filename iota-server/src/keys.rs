@@ -0,0 +1,639 @@
+//! Key handling: parsing key specs like `"C-x C-s"`, the keymap mapping
+//! sequences to commands, and the user keybinding config loaded from
+//! `keys.toml`.
+
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use iota_core::{Direction, EditorInput};
+
+use crate::protocol::{Key, KeyCode};
+
+/// One or more keys pressed in order, e.g. `C-x C-s`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeySequence(pub Vec<Key>);
+
+/// Parses a single key spec like `c`, `C-c`, `M-enter`, or `C-M-x`.
+fn parse_single_key(spec: &str) -> Option<Key> {
+    let mut ctrl = false;
+    let mut alt = false;
+    let mut rest = spec;
+
+    loop {
+        if let Some(stripped) = rest.strip_prefix("C-") {
+            ctrl = true;
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix("M-") {
+            alt = true;
+            rest = stripped;
+        } else {
+            break;
+        }
+    }
+
+    let code = match rest {
+        "enter" | "RET" => KeyCode::Enter,
+        "backspace" | "DEL" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "esc" | "ESC" => KeyCode::Esc,
+        "insert" => KeyCode::Insert,
+        "backtab" => KeyCode::BackTab,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        single => {
+            let mut chars = single.chars();
+            let c = chars.next()?;
+
+            if chars.next().is_some() {
+                return None;
+            }
+
+            KeyCode::Char(c)
+        }
+    };
+
+    Some(Key { code, ctrl, alt })
+}
+
+/// Parses a whitespace-separated key spec like `"C-x C-s"` into a
+/// [`KeySequence`].
+pub fn parse_key_spec(spec: &str) -> Option<KeySequence> {
+    let keys: Option<Vec<Key>> = spec.split_whitespace().map(parse_single_key).collect();
+
+    match keys {
+        Some(keys) if !keys.is_empty() => Some(KeySequence(keys)),
+        _ => None,
+    }
+}
+
+/// The editor command a named action stands for in `keys.toml`.
+pub fn action_to_input(name: &str) -> Option<EditorInput> {
+    let input = match name {
+        "save" => EditorInput::Save,
+        "quit" => EditorInput::Quit,
+        "force-quit" => EditorInput::ForceQuit,
+        "insert-newline" => EditorInput::InsertNewline,
+        "open-line-below" => EditorInput::OpenLineBelow,
+        "open-line-above" => EditorInput::OpenLineAbove,
+        "delete-char" => EditorInput::DeleteChar,
+        "delete-forward" => EditorInput::DeleteForward,
+        "undo" => EditorInput::Undo,
+        "redo" => EditorInput::Redo,
+        "repeat" => EditorInput::RepeatLast,
+        "count-words" => EditorInput::CountWords,
+        "sort-lines" => EditorInput::SortLines {
+            ignore_case: false,
+            reverse: false,
+        },
+        "sort-lines-ignore-case" => EditorInput::SortLines {
+            ignore_case: true,
+            reverse: false,
+        },
+        "reverse-sort-lines" => EditorInput::SortLines {
+            ignore_case: false,
+            reverse: true,
+        },
+        "normalize-blank-lines" => EditorInput::NormalizeBlankLines,
+        "toggle-comment" => EditorInput::ToggleComment,
+        "recenter" => EditorInput::Recenter,
+        "universal-argument" => EditorInput::UniversalArgument,
+        "start-macro" => EditorInput::StartMacro,
+        "end-macro" => EditorInput::EndMacro,
+        "play-macro" => EditorInput::PlayMacro,
+        "transpose-chars" => EditorInput::TransposeChars,
+        "kill-line" => EditorInput::KillLine,
+        "backward-kill-word" => EditorInput::DeleteWordBackward,
+        "kill-word" => EditorInput::DeleteWordForward,
+        "yank" => EditorInput::Yank,
+        "select-register" => EditorInput::SelectRegister,
+        "add-cursor-below" => EditorInput::AddCursorBelow,
+        "clear-cursors" => EditorInput::ClearCursors,
+        "cancel" => EditorInput::Cancel,
+        "toggle-overwrite" => EditorInput::ToggleOverwrite,
+        "split-window" => EditorInput::SplitWindow,
+        "other-window" => EditorInput::FocusOtherWindow,
+        "unsplit-window" => EditorInput::UnsplitWindow,
+        "beginning-of-buffer" => EditorInput::MoveBufferStart,
+        "end-of-buffer" => EditorInput::MoveBufferEnd,
+        "forward-paragraph" => EditorInput::MoveParagraphForward,
+        "backward-paragraph" => EditorInput::MoveParagraphBackward,
+        "move-up" => EditorInput::MoveCursor(Direction::Up),
+        "move-down" => EditorInput::MoveCursor(Direction::Down),
+        "move-left" => EditorInput::MoveCursor(Direction::Left),
+        "move-right" => EditorInput::MoveCursor(Direction::Right),
+        _ => return None,
+    };
+
+    Some(input)
+}
+
+/// Maps key sequences to editor commands. Later bindings for the same
+/// sequence replace earlier ones, so user config overrides the defaults.
+pub struct Keymap {
+    bindings: Vec<(KeySequence, EditorInput)>,
+}
+
+/// Outcome of looking up a partial key sequence.
+pub enum Lookup {
+    /// The sequence is bound to this command.
+    Match(EditorInput),
+    /// The sequence is a prefix of at least one binding; wait for more
+    /// keys.
+    Prefix,
+    /// Nothing starts with this sequence.
+    None,
+}
+
+impl Keymap {
+    /// The built-in bindings.
+    pub fn default_bindings() -> Keymap {
+        let mut keymap = Keymap {
+            bindings: Vec::new(),
+        };
+
+        for (spec, action) in &[
+            ("C-c", "quit"),
+            ("C-s", "save"),
+            ("up", "move-up"),
+            ("down", "move-down"),
+            ("left", "move-left"),
+            ("right", "move-right"),
+            ("enter", "insert-newline"),
+            ("backspace", "delete-char"),
+            ("delete", "delete-forward"),
+            ("C-u", "universal-argument"),
+            ("C-/", "undo"),
+            ("M-/", "redo"),
+            ("M-w", "count-words"),
+            ("M-;", "toggle-comment"),
+            ("C-l", "recenter"),
+            ("C-t", "transpose-chars"),
+            ("C-k", "kill-line"),
+            ("M-DEL", "backward-kill-word"),
+            ("C-backspace", "backward-kill-word"),
+            ("M-d", "kill-word"),
+            ("C-delete", "kill-word"),
+            ("C-y", "yank"),
+            ("C-x r", "select-register"),
+            ("M-down", "add-cursor-below"),
+            ("esc", "cancel"),
+            ("insert", "toggle-overwrite"),
+            ("M-<", "beginning-of-buffer"),
+            ("M->", "end-of-buffer"),
+            ("M-{", "backward-paragraph"),
+            ("M-}", "forward-paragraph"),
+            ("C-x (", "start-macro"),
+            ("C-x )", "end-macro"),
+            ("C-x e", "play-macro"),
+            ("C-x z", "repeat"),
+            ("C-x 2", "split-window"),
+            ("C-x o", "other-window"),
+            ("C-x 1", "unsplit-window"),
+        ] {
+            let sequence = parse_key_spec(spec).expect("default key spec parses");
+            let input = action_to_input(action).expect("default action exists");
+            keymap.bind(sequence, input);
+        }
+
+        keymap
+    }
+
+    /// Adds a binding, replacing any existing binding for the same
+    /// sequence.
+    pub fn bind(&mut self, sequence: KeySequence, input: EditorInput) {
+        self.bindings.retain(|(existing, _)| *existing != sequence);
+        self.bindings.push((sequence, input));
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        for (sequence, input) in &self.bindings {
+            if sequence.0 == keys {
+                return Lookup::Match(input.clone());
+            }
+        }
+
+        if self
+            .bindings
+            .iter()
+            .any(|(sequence, _)| sequence.0.len() > keys.len() && sequence.0.starts_with(keys))
+        {
+            return Lookup::Prefix;
+        }
+
+        Lookup::None
+    }
+}
+
+/// What a key press amounts to, given the pending prefix keys.
+pub enum KeyResult {
+    Input(EditorInput),
+    /// Start or middle of a multi-key binding; keep collecting.
+    Pending,
+    Unbound,
+}
+
+/// Feeds one key into the keymap. `pending` holds the keys of an
+/// in-progress multi-key sequence and is updated in place.
+pub fn process_key(key: Key, keymap: &Keymap, pending: &mut Vec<Key>) -> KeyResult {
+    // Escape abandons an in-flight chord instead of extending it, on
+    // top of whatever it is bound to on its own.
+    if key.code == KeyCode::Esc && !pending.is_empty() {
+        pending.clear();
+        return KeyResult::Input(EditorInput::Cancel);
+    }
+
+    pending.push(key);
+
+    match keymap.lookup(pending) {
+        Lookup::Match(input) => {
+            pending.clear();
+            KeyResult::Input(input)
+        }
+        Lookup::Prefix => KeyResult::Pending,
+        Lookup::None => {
+            let was_prefix = pending.len() > 1;
+            pending.clear();
+
+            if was_prefix {
+                return KeyResult::Unbound;
+            }
+
+            // Unbound single keys fall through to plain typing.
+            match key.code {
+                KeyCode::Char(c) if !key.ctrl && !key.alt => {
+                    KeyResult::Input(EditorInput::Insert(c))
+                }
+                _ => KeyResult::Unbound,
+            }
+        }
+    }
+}
+
+/// Keyboard-macro state for one client. Recording captures the raw keys
+/// rather than the commands they resolved to, so a replay goes back
+/// through whatever keymap is current at the time.
+#[derive(Default)]
+pub struct MacroState {
+    /// Keys captured so far, while a recording is in progress.
+    recording: Option<Vec<Key>>,
+    /// The in-flight (possibly multi-key) sequence, held back until it
+    /// resolves so the stop-recording binding doesn't record itself.
+    sequence: Vec<Key>,
+    /// The last finished recording, ready to replay.
+    recorded: Vec<Key>,
+}
+
+impl MacroState {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    fn start(&mut self) {
+        self.recording = Some(Vec::new());
+        self.sequence.clear();
+    }
+
+    /// Finishes the recording, returning how many keys it captured.
+    fn stop(&mut self) -> usize {
+        self.recorded = self.recording.take().unwrap_or_default();
+        self.sequence.clear();
+        self.recorded.len()
+    }
+}
+
+/// Feeds one key through the keymap with macro bookkeeping. The macro
+/// control bindings are consumed here and never reach the editor; a
+/// play expands into whatever inputs the recorded keys resolve to under
+/// the current keymap. Returns the inputs to execute plus an optional
+/// status message for the client.
+pub fn process_key_with_macros(
+    key: Key,
+    keymap: &Keymap,
+    pending: &mut Vec<Key>,
+    macros: &mut MacroState,
+) -> (Vec<EditorInput>, Option<String>) {
+    macros.sequence.push(key);
+
+    match process_key(key, keymap, pending) {
+        KeyResult::Pending => (Vec::new(), None),
+        KeyResult::Unbound => {
+            macros.sequence.clear();
+            (Vec::new(), None)
+        }
+        KeyResult::Input(EditorInput::StartMacro) => {
+            macros.start();
+            (Vec::new(), Some("Recording keyboard macro".to_string()))
+        }
+        KeyResult::Input(EditorInput::EndMacro) => {
+            if !macros.is_recording() {
+                macros.sequence.clear();
+                return (Vec::new(), Some("Not recording a macro".to_string()));
+            }
+
+            let keys = macros.stop();
+            (
+                Vec::new(),
+                Some(format!("Keyboard macro recorded ({} keys)", keys)),
+            )
+        }
+        KeyResult::Input(EditorInput::PlayMacro) => {
+            macros.sequence.clear();
+
+            if macros.recorded.is_empty() {
+                return (Vec::new(), Some("No keyboard macro recorded".to_string()));
+            }
+
+            // Replay through the keymap. A play binding that somehow made
+            // it into a recording is dropped rather than recursing.
+            let mut inputs = Vec::new();
+
+            for key in macros.recorded.clone() {
+                if let KeyResult::Input(input) = process_key(key, keymap, pending) {
+                    if !matches!(input, EditorInput::PlayMacro) {
+                        inputs.push(input);
+                    }
+                }
+            }
+
+            (inputs, None)
+        }
+        KeyResult::Input(input) => {
+            if let Some(recording) = &mut macros.recording {
+                recording.append(&mut macros.sequence);
+            } else {
+                macros.sequence.clear();
+            }
+
+            (vec![input], None)
+        }
+    }
+}
+
+/// Parses the `keys.toml` format: a flat table of key spec to action
+/// name. Returns the parsed bindings plus a warning per line that could
+/// not be understood.
+pub fn parse_keymap_config(contents: &str) -> (Vec<(KeySequence, EditorInput)>, Vec<String>) {
+    let mut bindings = Vec::new();
+    let mut warnings = Vec::new();
+
+    let table: toml::Table = match contents.parse() {
+        Ok(table) => table,
+        Err(err) => {
+            warnings.push(format!("keys.toml is not valid TOML: {}", err));
+            return (bindings, warnings);
+        }
+    };
+
+    for (spec, value) in table {
+        let action = match value.as_str() {
+            Some(action) => action,
+            None => {
+                warnings.push(format!("keys.toml: value for {:?} is not a string", spec));
+                continue;
+            }
+        };
+
+        let sequence = match parse_key_spec(&spec) {
+            Some(sequence) => sequence,
+            None => {
+                warnings.push(format!("keys.toml: cannot parse key spec {:?}", spec));
+                continue;
+            }
+        };
+
+        match action_to_input(action) {
+            Some(input) => bindings.push((sequence, input)),
+            None => warnings.push(format!(
+                "keys.toml: unknown action {:?} bound to {:?}",
+                action, spec
+            )),
+        }
+    }
+
+    (bindings, warnings)
+}
+
+/// Loads the default keymap with the user's `keys.toml` merged on top.
+/// Problems in the config produce warnings, never a panic.
+pub fn load_keymap() -> (Keymap, Vec<String>) {
+    let mut keymap = Keymap::default_bindings();
+
+    let contents = crate::get_config_dir()
+        .map(|dir| dir.join("keys.toml"))
+        .and_then(|path| fs::read_to_string(path).ok());
+
+    let mut warnings = Vec::new();
+
+    if let Some(contents) = contents {
+        let (bindings, config_warnings) = parse_keymap_config(&contents);
+        warnings = config_warnings;
+
+        for (sequence, input) in bindings {
+            keymap.bind(sequence, input);
+        }
+    }
+
+    (keymap, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modified_keys_and_sequences() {
+        assert_eq!(parse_key_spec("C-c"), Some(KeySequence(vec![Key::ctrl('c')])));
+        assert_eq!(
+            parse_key_spec("C-x C-s"),
+            Some(KeySequence(vec![Key::ctrl('x'), Key::ctrl('s')]))
+        );
+        assert_eq!(
+            parse_key_spec("M-enter"),
+            Some(KeySequence(vec![Key {
+                code: KeyCode::Enter,
+                ctrl: false,
+                alt: true,
+            }]))
+        );
+        assert!(parse_key_spec("C-").is_none());
+        assert!(parse_key_spec("xy").is_none());
+    }
+
+    #[test]
+    fn parses_insert_and_backtab() {
+        assert_eq!(
+            parse_key_spec("insert"),
+            Some(KeySequence(vec![Key::code(KeyCode::Insert)]))
+        );
+        assert_eq!(
+            parse_key_spec("backtab"),
+            Some(KeySequence(vec![Key::code(KeyCode::BackTab)]))
+        );
+    }
+
+    #[test]
+    fn config_round_trips_into_keymap_entries() {
+        let (bindings, warnings) = parse_keymap_config(
+            r#"
+            "C-w" = "save"
+            "C-x C-c" = "quit"
+            "#,
+        );
+
+        assert!(warnings.is_empty());
+        assert_eq!(bindings.len(), 2);
+
+        let mut keymap = Keymap::default_bindings();
+        for (sequence, input) in bindings {
+            keymap.bind(sequence, input);
+        }
+
+        assert!(matches!(
+            keymap.lookup(&[Key::ctrl('w')]),
+            Lookup::Match(EditorInput::Save)
+        ));
+        assert!(matches!(keymap.lookup(&[Key::ctrl('x')]), Lookup::Prefix));
+        assert!(matches!(
+            keymap.lookup(&[Key::ctrl('x'), Key::ctrl('c')]),
+            Lookup::Match(EditorInput::Quit)
+        ));
+    }
+
+    #[test]
+    fn bad_config_lines_warn_instead_of_panicking() {
+        let (bindings, warnings) = parse_keymap_config(
+            r#"
+            "C-w" = "save"
+            "C-bogus-spec extra" = "save"
+            "C-y" = "launch-missiles"
+            "#,
+        );
+
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn user_binding_overrides_the_default() {
+        let mut keymap = Keymap::default_bindings();
+        keymap.bind(
+            parse_key_spec("C-c").unwrap(),
+            EditorInput::Save,
+        );
+
+        assert!(matches!(
+            keymap.lookup(&[Key::ctrl('c')]),
+            Lookup::Match(EditorInput::Save)
+        ));
+    }
+
+    #[test]
+    fn a_digit_prefix_sequence_reaches_the_editor_in_order() {
+        let keymap = Keymap::default_bindings();
+        let mut pending = Vec::new();
+
+        // `C-u 5 down`: the editor accumulates the count; the keymap
+        // just has to pass each piece through.
+        assert!(matches!(
+            process_key(Key::ctrl('u'), &keymap, &mut pending),
+            KeyResult::Input(EditorInput::UniversalArgument)
+        ));
+        assert!(matches!(
+            process_key(Key::char('5'), &keymap, &mut pending),
+            KeyResult::Input(EditorInput::Insert('5'))
+        ));
+        assert!(matches!(
+            process_key(Key::code(KeyCode::Down), &keymap, &mut pending),
+            KeyResult::Input(EditorInput::MoveCursor(Direction::Down))
+        ));
+    }
+
+    #[test]
+    fn escape_abandons_a_pending_chord() {
+        let keymap = Keymap::default_bindings();
+        let mut pending = Vec::new();
+
+        assert!(matches!(
+            process_key(Key::ctrl('x'), &keymap, &mut pending),
+            KeyResult::Pending
+        ));
+        assert!(matches!(
+            process_key(Key::code(KeyCode::Esc), &keymap, &mut pending),
+            KeyResult::Input(EditorInput::Cancel)
+        ));
+        assert!(pending.is_empty());
+
+        // On its own, Escape resolves through its binding as usual.
+        assert!(matches!(
+            process_key(Key::code(KeyCode::Esc), &keymap, &mut pending),
+            KeyResult::Input(EditorInput::Cancel)
+        ));
+    }
+
+    #[test]
+    fn key_sequences_round_trip_through_serde() {
+        let sequence = parse_key_spec("C-x C-s").unwrap();
+        let json = serde_json::to_string(&sequence).unwrap();
+
+        assert_eq!(serde_json::from_str::<KeySequence>(&json).unwrap(), sequence);
+    }
+
+    #[test]
+    fn macros_record_keys_and_replay_through_the_keymap() {
+        let keymap = Keymap::default_bindings();
+        let mut pending = Vec::new();
+        let mut macros = MacroState::default();
+        let mut editor = iota_core::Editor::new();
+
+        let feed = |key, pending: &mut Vec<Key>, macros: &mut MacroState, editor: &mut iota_core::Editor| {
+            let (inputs, _) = process_key_with_macros(key, &keymap, pending, macros);
+            for input in inputs {
+                editor.execute_command(input);
+            }
+        };
+
+        // C-x ( a b C-x ) records typing "ab" without recording the
+        // start/stop bindings themselves.
+        feed(Key::ctrl('x'), &mut pending, &mut macros, &mut editor);
+        feed(Key::char('('), &mut pending, &mut macros, &mut editor);
+        feed(Key::char('a'), &mut pending, &mut macros, &mut editor);
+        feed(Key::char('b'), &mut pending, &mut macros, &mut editor);
+        feed(Key::ctrl('x'), &mut pending, &mut macros, &mut editor);
+        feed(Key::char(')'), &mut pending, &mut macros, &mut editor);
+        assert_eq!(editor.current_buffer().to_string(), "ab");
+
+        // C-x e replays it, doubling the text.
+        feed(Key::ctrl('x'), &mut pending, &mut macros, &mut editor);
+        feed(Key::char('e'), &mut pending, &mut macros, &mut editor);
+        assert_eq!(editor.current_buffer().to_string(), "abab");
+    }
+
+    #[test]
+    fn playing_with_nothing_recorded_reports_instead_of_replaying() {
+        let keymap = Keymap::default_bindings();
+        let mut pending = Vec::new();
+        let mut macros = MacroState::default();
+
+        process_key_with_macros(Key::ctrl('x'), &keymap, &mut pending, &mut macros);
+        let (inputs, status) =
+            process_key_with_macros(Key::char('e'), &keymap, &mut pending, &mut macros);
+
+        assert!(inputs.is_empty());
+        assert_eq!(status.as_deref(), Some("No keyboard macro recorded"));
+    }
+
+    #[test]
+    fn unbound_plain_chars_fall_through_to_insert() {
+        let keymap = Keymap::default_bindings();
+        let mut pending = Vec::new();
+
+        assert!(matches!(
+            process_key(Key::char('a'), &keymap, &mut pending),
+            KeyResult::Input(EditorInput::Insert('a'))
+        ));
+        assert!(pending.is_empty());
+    }
+}
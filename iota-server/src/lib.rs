@@ -0,0 +1,1126 @@
+//! The iota daemon: owns the [`Editor`] and serves any number of clients
+//! over a unix domain socket.
+
+pub mod finder;
+pub mod highlight;
+pub mod keys;
+pub mod protocol;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{broadcast, Notify, RwLock};
+
+use iota_core::{Editor, EditorEvent, EditorInput};
+
+use crate::keys::{Keymap, MacroState};
+use crate::protocol::{Key, Message, RenderData};
+
+/// Path of the socket iota listens on. The temp dir is shared between
+/// users on a multi-user machine, so the name carries the UID: two
+/// users each get their own server instead of colliding on one socket.
+pub fn get_socket_path() -> PathBuf {
+    // Safety: getuid cannot fail and touches no memory.
+    let uid = unsafe { libc::getuid() };
+
+    env::temp_dir().join(format!("iota-{}.sock", uid))
+}
+
+/// The directory iota's config files (`keys.toml`, `theme.toml`) live
+/// in: `$IOTA_CONFIG_DIR` if set, else `$XDG_CONFIG_HOME/iota`, else
+/// `~/.config/iota`. `None` only when none of those can be resolved,
+/// i.e. `HOME` is unset too.
+pub fn get_config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("IOTA_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("iota"));
+    }
+
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("iota"))
+}
+
+/// Like [`get_config_dir`], but creates the directory first, for callers
+/// about to write into it.
+pub fn ensure_config_dir() -> io::Result<PathBuf> {
+    let dir = get_config_dir().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "no config directory; set IOTA_CONFIG_DIR or HOME",
+        )
+    })?;
+
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Where the session snapshot lives: `session.json` in the config dir.
+/// `None` when no config dir can be resolved, in which case sessions
+/// are silently not persisted.
+fn session_file_path() -> Option<PathBuf> {
+    get_config_dir().map(|dir| dir.join("session.json"))
+}
+
+/// Where the per-file cursor cache lives: `view_state.json` next to the
+/// session snapshot. Same caveat as [`session_file_path`].
+fn view_state_file_path() -> Option<PathBuf> {
+    get_config_dir().map(|dir| dir.join("view_state.json"))
+}
+
+/// How often to auto-save modified file-backed buffers, taken from the
+/// `IOTA_AUTOSAVE_SECS` env var. Unset, zero, or unparsable means
+/// auto-save is off.
+fn autosave_period() -> Option<std::time::Duration> {
+    env::var("IOTA_AUTOSAVE_SECS")
+        .ok()?
+        .parse::<u64>()
+        .ok()
+        .filter(|&secs| secs > 0)
+        .map(std::time::Duration::from_secs)
+}
+
+pub struct Server {
+    editor: Arc<RwLock<Editor>>,
+    socket_path: PathBuf,
+    shutdown: Arc<Notify>,
+    /// Server-to-client push channel; every connected client holds a
+    /// subscription.
+    notifications: broadcast::Sender<Message>,
+    keymap: Arc<Keymap>,
+}
+
+impl Server {
+    /// Creates a server listening on the default local socket path.
+    ///
+    /// If the socket file already exists it is probed with
+    /// [`Message::ServerStatusCheck`]; a live server answering
+    /// [`Message::ServerStatusOk`] means another daemon owns the socket
+    /// and we refuse to start rather than clobber it. A stale socket
+    /// left behind by a crash gets no answer and is removed.
+    pub fn local() -> io::Result<Server> {
+        let socket_path = get_socket_path();
+
+        if socket_path.exists() {
+            if probe_socket(&socket_path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!(
+                        "an iota server is already running on {}",
+                        socket_path.display()
+                    ),
+                ));
+            }
+
+            // A previous instance died without cleaning up.
+            fs::remove_file(&socket_path)?;
+        }
+
+        Ok(Server::with_socket_path(socket_path))
+    }
+
+    fn with_socket_path(socket_path: PathBuf) -> Server {
+        let (notifications, _) = broadcast::channel(64);
+
+        let (keymap, warnings) = keys::load_keymap();
+        for warning in warnings {
+            eprintln!("{}", warning);
+        }
+
+        Server {
+            editor: Arc::new(RwLock::new(Editor::new())),
+            socket_path,
+            shutdown: Arc::new(Notify::new()),
+            notifications,
+            keymap: Arc::new(keymap),
+        }
+    }
+
+    /// Asks a running server to stop accepting connections and exit.
+    /// Safe to call from another task; also used by tests.
+    pub fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    /// Accepts and serves clients until a shutdown is requested, either
+    /// programmatically via [`Server::shutdown`] or by SIGINT/SIGTERM.
+    /// On the way out, modified buffers with a backing file are saved,
+    /// connected clients are sent [`Message::Shutdown`], and the socket
+    /// file is removed.
+    pub async fn run(&self) -> io::Result<()> {
+        let listener = UnixListener::bind(&self.socket_path)?;
+
+        // The server is per-user; keep other users on the machine from
+        // connecting to (or probing) it.
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.socket_path, fs::Permissions::from_mode(0o600))?;
+        }
+        let mut sigint = signal(SignalKind::interrupt())?;
+        let mut sigterm = signal(SignalKind::terminate())?;
+
+        // The interval always exists so select has something to poll,
+        // but its branch is disabled unless auto-save is configured.
+        let autosave_period = autosave_period();
+        let mut autosave = tokio::time::interval(
+            autosave_period.unwrap_or(std::time::Duration::from_secs(3600)),
+        );
+        autosave.tick().await; // the first tick fires immediately
+
+        // Swap files are rewritten at most once a second, off the key
+        // handling path, so a crash loses no more than that.
+        let mut swap = tokio::time::interval(std::time::Duration::from_secs(1));
+        swap.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = swap.tick() => {
+                    self.editor.read().await.write_swap_files();
+                }
+                _ = autosave.tick(), if autosave_period.is_some() => {
+                    // Holds the write lock only for the saves themselves,
+                    // so key handling isn't noticeably blocked.
+                    let saved = self.editor.write().await.save_modified_buffers();
+
+                    if saved > 0 {
+                        eprintln!("auto-saved {} buffer(s)", saved);
+                    }
+                }
+                _ = sigint.recv() => break,
+                _ = sigterm.recv() => break,
+                _ = self.shutdown.notified() => break,
+                accepted = listener.accept() => {
+                    let (stream, _addr) = accepted?;
+                    let editor = Arc::clone(&self.editor);
+                    let notifications = self.notifications.clone();
+                    let shutdown = Arc::clone(&self.shutdown);
+                    let keymap = Arc::clone(&self.keymap);
+
+                    tokio::spawn(async move {
+                        if let Err(err) =
+                            serve_connection(stream, editor, notifications, shutdown, keymap).await
+                        {
+                            eprintln!("client error: {}", err);
+                        }
+                    });
+                }
+            }
+        }
+
+        self.cleanup().await
+    }
+
+    /// Reopens the files recorded by the previous session, if a
+    /// snapshot exists. Meant to run once, before [`Server::run`].
+    pub async fn restore_session(&self) {
+        // The cursor cache loads first so the session's files, and
+        // anything opened later, land on their remembered positions.
+        if let Some(view_state) = view_state_file_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str::<iota_core::ViewStateCache>(&json).ok())
+        {
+            self.editor.write().await.restore_view_state(view_state);
+        }
+
+        let session = match session_file_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str::<iota_core::Session>(&json).ok())
+        {
+            Some(session) => session,
+            None => return,
+        };
+
+        self.editor.write().await.restore_session(&session);
+    }
+
+    /// Writes the current session snapshot to the config dir, replacing
+    /// any previous one. Runs automatically on shutdown and can be
+    /// called on demand. Best-effort: an unwritable config dir loses
+    /// the session, never the shutdown.
+    pub async fn save_session(&self) {
+        let (session, view_state) = {
+            let editor = self.editor.read().await;
+            (editor.save_session(), editor.view_state().clone())
+        };
+
+        if ensure_config_dir().is_err() {
+            return;
+        }
+
+        if let (Some(path), Ok(json)) = (session_file_path(), serde_json::to_string_pretty(&session))
+        {
+            let _ = fs::write(path, json);
+        }
+
+        if let (Some(path), Ok(json)) = (
+            view_state_file_path(),
+            serde_json::to_string_pretty(&view_state),
+        ) {
+            let _ = fs::write(path, json);
+        }
+    }
+
+    async fn cleanup(&self) -> io::Result<()> {
+        // Best-effort: save what we can, tell clients, drop the socket.
+        self.save_session().await;
+
+        let mut editor = self.editor.write().await;
+        editor.save_modified_buffers();
+        drop(editor);
+
+        let _ = self.notifications.send(Message::Shutdown);
+
+        if self.socket_path.exists() {
+            fs::remove_file(&self.socket_path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns true if a live server answers a status check on `socket_path`.
+/// Uses blocking I/O with a short timeout so it can run outside the
+/// runtime; also used by clients deciding whether to spawn a daemon.
+pub fn probe_socket(socket_path: &std::path::Path) -> bool {
+    use std::time::Duration;
+
+    let mut stream = match std::os::unix::net::UnixStream::connect(socket_path) {
+        Ok(stream) => stream,
+        Err(_) => return false,
+    };
+
+    let _ = stream.set_read_timeout(Some(Duration::from_millis(500)));
+
+    if protocol::write_message_blocking(&mut stream, &Message::ServerStatusCheck).is_err() {
+        return false;
+    }
+
+    matches!(
+        protocol::read_message_blocking(&mut stream),
+        Ok(Message::ServerStatusOk)
+    )
+}
+
+/// Dispatches a fresh connection to the right protocol handler. Scripts
+/// may speak newline-delimited JSON instead of the length-framed
+/// protocol; a framed message always begins with the high byte of its
+/// length prefix, so a first byte of `{` or `"` can only be JSON.
+async fn serve_connection(
+    mut stream: UnixStream,
+    editor: Arc<RwLock<Editor>>,
+    notifications: broadcast::Sender<Message>,
+    shutdown: Arc<Notify>,
+    keymap: Arc<Keymap>,
+) -> io::Result<()> {
+    let mut first = [0u8; 1];
+    if stream.read(&mut first).await? == 0 {
+        return Ok(());
+    }
+
+    // The sniffed byte is chained back in front of the rest of the
+    // stream, so neither handler has to know it was consumed.
+    let (read_half, write_half) = stream.into_split();
+    let reader = io::Cursor::new(vec![first[0]]).chain(read_half);
+
+    if first[0] == b'{' || first[0] == b'"' {
+        handle_json_client(reader, write_half, editor, notifications, shutdown, keymap).await
+    } else {
+        handle_client(reader, write_half, editor, notifications, shutdown, keymap).await
+    }
+}
+
+/// Serves one client connection: reads framed messages, applies them to
+/// the shared editor, and writes back direct replies plus any state
+/// updates broadcast by other clients' edits.
+async fn handle_client<R, W>(
+    mut reader: R,
+    mut writer: W,
+    editor: Arc<RwLock<Editor>>,
+    notifications: broadcast::Sender<Message>,
+    shutdown: Arc<Notify>,
+    keymap: Arc<Keymap>,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut pushed_rx = notifications.subscribe();
+    // Keys of a multi-key binding in progress, per client.
+    let mut pending_keys: Vec<Key> = Vec::new();
+    // Keyboard-macro recording, also per client.
+    let mut macros = MacroState::default();
+    // The full panes this client last saw, so state updates can be sent
+    // as row diffs against them.
+    let mut last_panes: Vec<RenderData> = Vec::new();
+
+    loop {
+        tokio::select! {
+            read = protocol::read_message(&mut reader) => {
+                let message = match read {
+                    Ok(message) => message,
+                    // Client hung up.
+                    Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                    Err(err) => return Err(err),
+                };
+
+                let replies = handle_message(
+                    message,
+                    &editor,
+                    &notifications,
+                    &shutdown,
+                    &keymap,
+                    &mut pending_keys,
+                    &mut macros,
+                )
+                .await;
+
+                for reply in replies {
+                    let reply = match reply {
+                        Message::State(panes) => diff_state(&mut last_panes, panes),
+                        other => other,
+                    };
+                    protocol::write_message(&mut writer, &reply).await?;
+                }
+            }
+            pushed = pushed_rx.recv() => {
+                if let Ok(message) = pushed {
+                    let is_shutdown = message == Message::Shutdown;
+                    let message = match message {
+                        Message::State(panes) => diff_state(&mut last_panes, panes),
+                        other => other,
+                    };
+                    protocol::write_message(&mut writer, &message).await?;
+
+                    if is_shutdown {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Serves a client speaking newline-delimited JSON: every line in is one
+/// [`Message`], every reply or pushed update is one line back. Meant for
+/// scripting the editor; state updates are always sent whole, never as
+/// row diffs.
+async fn handle_json_client<R, W>(
+    reader: R,
+    mut writer: W,
+    editor: Arc<RwLock<Editor>>,
+    notifications: broadcast::Sender<Message>,
+    shutdown: Arc<Notify>,
+    keymap: Arc<Keymap>,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+    let mut pushed_rx = notifications.subscribe();
+    let mut pending_keys: Vec<Key> = Vec::new();
+    let mut macros = MacroState::default();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = match line? {
+                    Some(line) => line,
+                    None => return Ok(()),
+                };
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let message = match serde_json::from_str(&line) {
+                    Ok(message) => message,
+                    Err(err) => {
+                        let error = Message::Error(format!("bad command: {}", err));
+                        write_json_line(&mut writer, &error).await?;
+                        continue;
+                    }
+                };
+
+                let replies = handle_message(
+                    message,
+                    &editor,
+                    &notifications,
+                    &shutdown,
+                    &keymap,
+                    &mut pending_keys,
+                    &mut macros,
+                )
+                .await;
+
+                for reply in replies {
+                    write_json_line(&mut writer, &reply).await?;
+                }
+            }
+            pushed = pushed_rx.recv() => {
+                if let Ok(message) = pushed {
+                    let is_shutdown = message == Message::Shutdown;
+                    write_json_line(&mut writer, &message).await?;
+
+                    if is_shutdown {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes one message as a JSON line for a scripting client.
+async fn write_json_line<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    line.push(b'\n');
+
+    writer.write_all(&line).await
+}
+
+/// Turns a full set of panes into a [`Message::State`] that ships only
+/// the rows that changed since this client's previous update, recording
+/// the full panes in `last_panes` for the next diff. A pane whose shape
+/// changed — scroll position, line count, focus — is sent whole with
+/// `changed_lines: None`, as is everything on first contact.
+fn diff_state(last_panes: &mut Vec<RenderData>, panes: Vec<RenderData>) -> Message {
+    let diffed = panes
+        .iter()
+        .enumerate()
+        .map(|(i, pane)| {
+            let prev = match last_panes.get(i) {
+                Some(prev)
+                    if prev.lines.len() == pane.lines.len()
+                        && prev.scroll_line == pane.scroll_line
+                        && prev.scroll_column == pane.scroll_column
+                        && prev.focused == pane.focused =>
+                {
+                    prev
+                }
+                _ => return pane.clone(),
+            };
+
+            let rows: Vec<usize> = pane
+                .lines
+                .iter()
+                .enumerate()
+                .filter(|&(row, line)| prev.lines[row] != *line)
+                .map(|(row, _)| row)
+                .collect();
+
+            // Highlights ride along with their rows, so a patched line
+            // never keeps stale styling.
+            let lines = rows.iter().map(|&row| pane.lines[row].clone()).collect();
+            let highlights = rows
+                .iter()
+                .map(|&row| pane.highlights.get(row).cloned().unwrap_or_default())
+                .collect();
+
+            RenderData {
+                lines,
+                changed_lines: Some(rows),
+                highlights,
+                ..pane.clone()
+            }
+        })
+        .collect();
+
+    *last_panes = panes;
+    Message::State(diffed)
+}
+
+/// Handles one message from a client, returning replies that should go
+/// only to that client. State changes are broadcast to every client via
+/// `notifications` instead, so all connected terminals redraw.
+#[allow(clippy::too_many_arguments)]
+async fn handle_message(
+    message: Message,
+    editor: &Arc<RwLock<Editor>>,
+    notifications: &broadcast::Sender<Message>,
+    shutdown: &Arc<Notify>,
+    keymap: &Keymap,
+    pending_keys: &mut Vec<Key>,
+    macros: &mut MacroState,
+) -> Vec<Message> {
+    match message {
+        Message::ClientStart => {
+            let editor = editor.read().await;
+            vec![Message::State(render_data(&editor))]
+        }
+        Message::KeyPress(key) => {
+            let (mut inputs, status) =
+                keys::process_key_with_macros(key, keymap, pending_keys, macros);
+            let mut replies: Vec<Message> = status.into_iter().map(Message::Info).collect();
+
+            match inputs.len() {
+                0 => replies,
+                1 => {
+                    let input_replies =
+                        apply_input(inputs.remove(0), editor, notifications, shutdown).await;
+                    replies.extend(input_replies);
+                    replies
+                }
+                // More than one input means a macro replay; run it like
+                // a batch, under one lock.
+                _ => {
+                    let run_replies = run_inputs(inputs, editor, notifications, shutdown).await;
+                    replies.extend(run_replies);
+                    replies
+                }
+            }
+        }
+        Message::KeyBatch { keys } => {
+            let mut inputs = Vec::new();
+            let mut replies = Vec::new();
+
+            for key in keys {
+                let (mut key_inputs, status) =
+                    keys::process_key_with_macros(key, keymap, pending_keys, macros);
+                inputs.append(&mut key_inputs);
+                replies.extend(status.map(Message::Info));
+            }
+
+            if inputs.is_empty() {
+                return replies;
+            }
+
+            let run_replies = run_inputs(inputs, editor, notifications, shutdown).await;
+            replies.extend(run_replies);
+            replies
+        }
+        Message::Paste { text } => {
+            apply_input(EditorInput::Paste(text), editor, notifications, shutdown).await
+        }
+        Message::Open { path, line } => {
+            let mut replies =
+                apply_input(EditorInput::OpenFile(path), editor, notifications, shutdown).await;
+
+            if let Some(line) = line {
+                let goto_replies =
+                    apply_input(EditorInput::GotoLine(line), editor, notifications, shutdown)
+                        .await;
+                replies.extend(goto_replies);
+            }
+
+            replies
+        }
+        Message::OpenScratch { name, contents } => {
+            apply_input(
+                EditorInput::OpenScratch { name, contents },
+                editor,
+                notifications,
+                shutdown,
+            )
+            .await
+        }
+        Message::ShellPipe { command } => shell_pipe(command, editor, notifications).await,
+        Message::ListFiles => {
+            // The walk is bounded by the finder's depth and count caps
+            // and runs on a blocking thread, so it never holds up the
+            // editor lock or the runtime.
+            let root = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+            let files = tokio::task::spawn_blocking(move || finder::list_files(&root))
+                .await
+                .unwrap_or_default();
+
+            vec![Message::FileList(files)]
+        }
+        Message::MouseClick { line, column } => {
+            let mut editor = editor.write().await;
+            editor.execute_command(EditorInput::SetCursor(line, column));
+            let _ = notifications.send(Message::State(render_data(&editor)));
+            Vec::new()
+        }
+        Message::MouseDrag { line, column } => {
+            let mut editor = editor.write().await;
+            editor.execute_command(EditorInput::ExtendSelection(line, column));
+            let _ = notifications.send(Message::State(render_data(&editor)));
+            Vec::new()
+        }
+        Message::MouseUp => {
+            let mut editor = editor.write().await;
+            editor.execute_command(EditorInput::EndSelection);
+            Vec::new()
+        }
+        Message::Scroll { delta } => {
+            let mut editor = editor.write().await;
+            editor.execute_command(EditorInput::Scroll(delta));
+            let _ = notifications.send(Message::State(render_data(&editor)));
+            Vec::new()
+        }
+        Message::Resize { width, height } => {
+            let mut editor = editor.write().await;
+            editor.execute_command(EditorInput::Resize(width, height));
+            let _ = notifications.send(Message::State(render_data(&editor)));
+            Vec::new()
+        }
+        Message::ServerStatusCheck => vec![Message::ServerStatusOk],
+        // Server-to-client messages arriving from a client are ignored.
+        _ => Vec::new(),
+    }
+}
+
+/// Executes a run of inputs under one editor lock, broadcasting at most
+/// one state update. Unlike single presses, a key batch or macro replay
+/// never redraws mid-run. Used by `Message::KeyBatch` and macro replays.
+async fn run_inputs(
+    inputs: Vec<EditorInput>,
+    editor: &Arc<RwLock<Editor>>,
+    notifications: &broadcast::Sender<Message>,
+    shutdown: &Arc<Notify>,
+) -> Vec<Message> {
+    let mut editor = editor.write().await;
+    let mut replies = Vec::new();
+    let mut render = false;
+
+    for input in inputs {
+        match editor.execute_command(input) {
+            EditorEvent::Render => render = true,
+            EditorEvent::Bell => replies.push(Message::Bell),
+            EditorEvent::Info(msg) => {
+                render = true;
+                replies.push(Message::Info(msg));
+            }
+            // The wire keeps errors as plain strings; the structure only
+            // matters in-process.
+            EditorEvent::Error(err) => replies.push(Message::Error(err.to_string())),
+            EditorEvent::Shutdown => {
+                shutdown.notify_one();
+                return replies;
+            }
+        }
+    }
+
+    if render {
+        let _ = notifications.send(Message::State(render_data(&editor)));
+    }
+
+    replies
+}
+
+/// Runs one command against the editor and translates the resulting
+/// event: state changes are broadcast to all clients, messages go back
+/// to the sender, and a shutdown event stops the server.
+async fn apply_input(
+    input: EditorInput,
+    editor: &Arc<RwLock<Editor>>,
+    notifications: &broadcast::Sender<Message>,
+    shutdown: &Arc<Notify>,
+) -> Vec<Message> {
+    // Saves are special-cased so the disk write happens outside the
+    // editor lock; see `save_without_blocking`.
+    if matches!(input, EditorInput::Save) {
+        return save_without_blocking(editor, notifications).await;
+    }
+
+    let mut editor = editor.write().await;
+    let event = editor.execute_command(input);
+
+    match event {
+        EditorEvent::Render => {
+            let _ = notifications.send(Message::State(render_data(&editor)));
+            Vec::new()
+        }
+        EditorEvent::Info(msg) => {
+            let _ = notifications.send(Message::State(render_data(&editor)));
+            vec![Message::Info(msg)]
+        }
+        EditorEvent::Bell => vec![Message::Bell],
+        EditorEvent::Error(err) => vec![Message::Error(err.to_string())],
+        EditorEvent::Shutdown => {
+            // Cleanup in `run` broadcasts the Shutdown to clients.
+            shutdown.notify_one();
+            Vec::new()
+        }
+    }
+}
+
+/// How long a shell-pipe command may run before it is killed.
+const SHELL_PIPE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Runs `command` through the shell with the selection (or the whole
+/// buffer when there is none) as stdin, and splices its stdout back in
+/// as one undo unit. The subprocess runs with no editor lock held; only
+/// the snapshot and the final splice take one. A nonzero exit reports
+/// stderr and leaves the buffer untouched, and a command that never
+/// finishes is killed after [`SHELL_PIPE_TIMEOUT`].
+async fn shell_pipe(
+    command: String,
+    editor: &Arc<RwLock<Editor>>,
+    notifications: &broadcast::Sender<Message>,
+) -> Vec<Message> {
+    let (id, start, end, input) = editor.read().await.pipe_region();
+
+    let output = match tokio::time::timeout(SHELL_PIPE_TIMEOUT, run_shell(&command, input)).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(err)) => return vec![Message::Error(format!("shell-pipe failed: {}", err))],
+        Err(_) => {
+            return vec![Message::Error(format!(
+                "shell-pipe: command timed out after {}s",
+                SHELL_PIPE_TIMEOUT.as_secs()
+            ))]
+        }
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return vec![Message::Error(format!(
+            "shell-pipe exited with {}: {}",
+            output.status,
+            stderr.trim()
+        ))];
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let mut editor = editor.write().await;
+    editor.replace_region(id, start, end, &stdout);
+    let _ = notifications.send(Message::State(render_data(&editor)));
+
+    Vec::new()
+}
+
+/// Spawns `sh -c command`, feeds it `input`, and collects its output.
+/// Writing and waiting run concurrently so a command that doesn't read
+/// all of its stdin can't deadlock against us, and the child is killed
+/// if the surrounding timeout drops this future.
+async fn run_shell(command: &str, input: String) -> io::Result<std::process::Output> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let write = async move {
+        let _ = stdin.write_all(input.as_bytes()).await;
+        // Dropping stdin closes it, so the command sees EOF.
+    };
+
+    let (_, output) = tokio::join!(write, child.wait_with_output());
+    output
+}
+
+/// Saves the focused buffer without holding the editor lock across the
+/// disk write, so a slow filesystem can't freeze other clients'
+/// keystrokes. The contents are snapshotted under a read lock, written
+/// on a blocking thread, and the modified flag cleared with a short
+/// re-lock at the end.
+async fn save_without_blocking(
+    editor: &Arc<RwLock<Editor>>,
+    notifications: &broadcast::Sender<Message>,
+) -> Vec<Message> {
+    let (id, path, contents) = {
+        let editor = editor.read().await;
+        let buffer = editor.current_buffer();
+
+        match buffer.snapshot_for_save() {
+            Some((path, contents)) => (buffer.id(), path, contents),
+            // Same wording the editor uses for a pathless save.
+            None => return vec![Message::Info("No file name; use save-as".to_string())],
+        }
+    };
+
+    let written =
+        tokio::task::spawn_blocking(move || iota_core::buffer::write_snapshot(&path, &contents))
+            .await;
+
+    match written {
+        Ok(Ok(())) => {
+            let mut editor = editor.write().await;
+            if let Some(buffer) = editor.buffer_mut(id) {
+                buffer.mark_saved();
+            }
+            editor.remember_view_state();
+
+            let _ = notifications.send(Message::State(render_data(&editor)));
+            vec![Message::Info("Saved".to_string())]
+        }
+        Ok(Err(err)) => vec![Message::Error(format!("Save failed: {}", err))],
+        Err(err) => vec![Message::Error(format!("Save failed: {}", err))],
+    }
+}
+
+/// Render data for every visible window, left to right. Selection and
+/// secondary-cursor details are only reported for the focused window,
+/// which is where they can be acted on.
+fn render_data(editor: &Editor) -> Vec<RenderData> {
+    let (buffer_index, total_buffers, modified_buffers) = editor.buffer_counts();
+
+    editor
+        .windows()
+        .iter()
+        .enumerate()
+        .map(|(i, &view_index)| {
+            let view = &editor.views()[view_index];
+            let buffer = editor
+                .buffer(view.buffer_id)
+                .expect("window points at a live buffer");
+            let focused = i == editor.focused_window();
+
+            let lines = buffer.get_lines();
+            let highlighter = buffer
+                .filepath
+                .as_deref()
+                .and_then(|path| highlight::registry().for_path(path));
+            let highlights = match highlighter {
+                Some(highlighter) => lines
+                    .iter()
+                    .map(|line| {
+                        highlighter
+                            .spans(line)
+                            .into_iter()
+                            .map(|(range, color)| (range.start, range.end, color))
+                            .collect()
+                    })
+                    .collect(),
+                None => vec![Vec::new(); lines.len()],
+            };
+
+            RenderData {
+                lines,
+                changed_lines: None,
+                highlights,
+                cursor: view.cursor,
+                scroll_line: view.scroll_line,
+                scroll_column: view.scroll_column,
+                char_count: buffer.len_chars(),
+                content_hash: buffer.content_hash(),
+                selection_chars: if focused {
+                    editor.selection_char_range().map(|(start, end)| end - start)
+                } else {
+                    None
+                },
+                secondary_cursors: if focused {
+                    editor.secondary_cursor_positions()
+                } else {
+                    Vec::new()
+                },
+                focused,
+                overwrite: editor.overwrite(),
+                modified: buffer.is_modified(),
+                name: buffer.display_name(),
+                filepath: buffer.filepath.clone(),
+                line_ending: if buffer.uses_crlf() { "CRLF" } else { "LF" }.to_string(),
+                encoding: if buffer.had_bom() { "UTF-8 BOM" } else { "UTF-8" }.to_string(),
+                buffer_index,
+                total_buffers,
+                modified_buffers,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn shutdown_stops_run_and_removes_the_socket() {
+        let socket_path = env::temp_dir().join(format!("iota-test-{}.sock", std::process::id()));
+        let server = Arc::new(Server::with_socket_path(socket_path.clone()));
+
+        let handle = {
+            let server = Arc::clone(&server);
+            tokio::spawn(async move { server.run().await })
+        };
+
+        // Give the listener a moment to bind, then ask it to stop.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(socket_path.exists());
+
+        server.shutdown();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("run did not return after shutdown")
+            .unwrap();
+
+        assert!(result.is_ok());
+        assert!(!socket_path.exists());
+    }
+
+    #[tokio::test]
+    async fn json_lines_clients_can_script_the_editor() {
+        let socket_path =
+            env::temp_dir().join(format!("iota-json-test-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&socket_path);
+        let server = Arc::new(Server::with_socket_path(socket_path.clone()));
+
+        let handle = {
+            let server = Arc::clone(&server);
+            tokio::spawn(async move { server.run().await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let stream = UnixStream::connect(&socket_path).await.unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut lines = BufReader::new(read_half).lines();
+
+        // A unit variant serializes as a bare string; both spellings
+        // must route to the JSON handler.
+        write_half.write_all(b"\"ClientStart\"\n").await.unwrap();
+        write_half
+            .write_all(b"{\"Paste\":{\"text\":\"hi\"}}\n")
+            .await
+            .unwrap();
+
+        let mut saw_paste = false;
+        while let Ok(Ok(Some(line))) =
+            tokio::time::timeout(Duration::from_secs(2), lines.next_line()).await
+        {
+            if let Ok(Message::State(panes)) = serde_json::from_str::<Message>(&line) {
+                if panes.iter().any(|p| p.lines.iter().any(|l| l == "hi")) {
+                    saw_paste = true;
+                    break;
+                }
+            }
+        }
+
+        assert!(saw_paste, "no state update showed the pasted text");
+
+        server.shutdown();
+        let _ = handle.await;
+    }
+
+    #[tokio::test]
+    async fn shell_pipe_transforms_the_selection_as_one_undo_unit() {
+        let editor = Arc::new(RwLock::new(Editor::new()));
+        let (notifications, _keep_alive) = broadcast::channel(8);
+
+        {
+            let mut editor = editor.write().await;
+            editor.execute_command(EditorInput::Paste("hello world\n".into()));
+            editor.execute_command(EditorInput::SetCursor(0, 0));
+            editor.execute_command(EditorInput::ExtendSelection(0, 5));
+        }
+
+        let replies = shell_pipe("tr a-z A-Z".to_string(), &editor, &notifications).await;
+        assert!(replies.is_empty(), "unexpected replies: {:?}", replies);
+
+        let mut editor = editor.write().await;
+        assert_eq!(editor.current_buffer().to_string(), "HELLO world\n");
+
+        // The whole splice undoes in one step.
+        editor.execute_command(EditorInput::Undo);
+        assert_eq!(editor.current_buffer().to_string(), "hello world\n");
+    }
+
+    #[tokio::test]
+    async fn a_failing_shell_pipe_leaves_the_buffer_untouched() {
+        let editor = Arc::new(RwLock::new(Editor::new()));
+        let (notifications, _keep_alive) = broadcast::channel(8);
+
+        editor
+            .write()
+            .await
+            .execute_command(EditorInput::Paste("keep me\n".into()));
+
+        let replies = shell_pipe(
+            "echo oops >&2; exit 3".to_string(),
+            &editor,
+            &notifications,
+        )
+        .await;
+
+        assert!(
+            matches!(&replies[..], [Message::Error(msg)] if msg.contains("oops")),
+            "unexpected replies: {:?}",
+            replies
+        );
+        assert_eq!(editor.read().await.current_buffer().to_string(), "keep me\n");
+    }
+
+    #[test]
+    fn socket_path_is_scoped_to_the_current_user() {
+        let uid = unsafe { libc::getuid() };
+        let path = get_socket_path();
+
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .contains(&uid.to_string()));
+    }
+
+    #[test]
+    fn iota_config_dir_overrides_the_xdg_resolution() {
+        // Resolution order is IOTA_CONFIG_DIR, XDG_CONFIG_HOME/iota,
+        // ~/.config/iota; only the first is safe to poke at here since
+        // other tests may load config concurrently.
+        env::set_var("IOTA_CONFIG_DIR", "/tmp/iota-config-test");
+        assert_eq!(
+            get_config_dir(),
+            Some(PathBuf::from("/tmp/iota-config-test"))
+        );
+        env::remove_var("IOTA_CONFIG_DIR");
+    }
+
+    fn pane(lines: &[&str]) -> RenderData {
+        RenderData {
+            lines: lines.iter().map(|s| s.to_string()).collect(),
+            changed_lines: None,
+            highlights: vec![Vec::new(); lines.len()],
+            cursor: (0, 0),
+            scroll_line: 0,
+            scroll_column: 0,
+            char_count: 0,
+            content_hash: 0,
+            selection_chars: None,
+            secondary_cursors: Vec::new(),
+            focused: true,
+            overwrite: false,
+            modified: false,
+            name: "test".to_string(),
+            filepath: None,
+            line_ending: "LF".to_string(),
+            encoding: "UTF-8".to_string(),
+            buffer_index: 1,
+            total_buffers: 1,
+            modified_buffers: 0,
+        }
+    }
+
+    #[test]
+    fn state_updates_ship_only_changed_rows() {
+        let mut last = Vec::new();
+
+        // First contact goes out whole.
+        let panes = match diff_state(&mut last, vec![pane(&["a", "b", "c"])]) {
+            Message::State(panes) => panes,
+            other => panic!("expected State, got {:?}", other),
+        };
+        assert_eq!(panes[0].changed_lines, None);
+        assert_eq!(panes[0].lines.len(), 3);
+
+        // One edited line: only that row is shipped.
+        let panes = match diff_state(&mut last, vec![pane(&["a", "B", "c"])]) {
+            Message::State(panes) => panes,
+            other => panic!("expected State, got {:?}", other),
+        };
+        assert_eq!(panes[0].changed_lines, Some(vec![1]));
+        assert_eq!(panes[0].lines, vec!["B".to_string()]);
+
+        // A scroll changes the pane's shape and forces a full send.
+        let mut scrolled = pane(&["a", "B", "c"]);
+        scrolled.scroll_line = 1;
+        let panes = match diff_state(&mut last, vec![scrolled]) {
+            Message::State(panes) => panes,
+            other => panic!("expected State, got {:?}", other),
+        };
+        assert_eq!(panes[0].changed_lines, None);
+        assert_eq!(panes[0].lines.len(), 3);
+    }
+}
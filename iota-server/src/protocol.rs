@@ -0,0 +1,317 @@
+//! The wire protocol spoken between the server and its clients. Messages
+//! are serialized as JSON and framed with a big-endian u32 length prefix.
+//! The framing helpers here are the only place that prefix is read or
+//! written, so the two sides cannot drift apart.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// A key press as reported by a client. Frontends translate their native
+/// key events into this before sending them over the socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Key {
+    pub fn char(c: char) -> Key {
+        Key {
+            code: KeyCode::Char(c),
+            ctrl: false,
+            alt: false,
+        }
+    }
+
+    pub fn ctrl(c: char) -> Key {
+        Key {
+            code: KeyCode::Char(c),
+            ctrl: true,
+            alt: false,
+        }
+    }
+
+    pub fn code(code: KeyCode) -> Key {
+        Key {
+            code,
+            ctrl: false,
+            alt: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyCode {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Esc,
+    Insert,
+    /// Shift-Tab, as terminals deliver it.
+    BackTab,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// What a highlighted span of text is, semantically. The server ships
+/// these instead of concrete styles so each frontend can map them onto
+/// its own palette; see [`crate::highlight`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HighlightColor {
+    Comment,
+    Number,
+}
+
+/// Everything the terminal needs to draw one frame of the current view.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderData {
+    /// The buffer's lines — all of them, unless `changed_lines` is set,
+    /// in which case only the rows listed there are shipped (in the same
+    /// order) and the client patches them into its last full copy.
+    pub lines: Vec<String>,
+    /// Row indices of the entries in `lines` when this update is a diff
+    /// against the previous one sent to the same client. `None` means a
+    /// full redraw (scroll, resize, first contact).
+    pub changed_lines: Option<Vec<usize>>,
+    /// Syntax highlight runs, one entry per entry in `lines`: `(start,
+    /// end, color)` half-open char ranges into that line. Empty for
+    /// buffers with no highlighter attached.
+    pub highlights: Vec<Vec<(usize, usize, HighlightColor)>>,
+    /// Zero-indexed `(line, column)` cursor position.
+    pub cursor: (usize, usize),
+    /// First buffer line visible in the view.
+    pub scroll_line: usize,
+    /// First column of each line visible in the view, for horizontal
+    /// scrolling of long lines.
+    pub scroll_column: usize,
+    /// Total chars in the buffer.
+    pub char_count: usize,
+    /// Hash of the whole buffer's contents, stable for identical text.
+    /// Clients that post-process the buffer can skip the work when the
+    /// hash hasn't moved since the last update.
+    pub content_hash: u64,
+    /// Chars covered by the active selection, when there is one. Shown as
+    /// "N selected" in place of the total.
+    pub selection_chars: Option<usize>,
+    /// Extra caret positions for multi-cursor editing, drawn dimmer than
+    /// the real terminal cursor.
+    pub secondary_cursors: Vec<(usize, usize)>,
+    /// Whether this window holds focus. Exactly one window per state
+    /// update is focused; the terminal's cursor lives there.
+    pub focused: bool,
+    /// Whether overwrite mode is on, shown as `OVR` vs `INS` in the
+    /// status line.
+    pub overwrite: bool,
+    /// Whether this pane's buffer has unsaved changes.
+    pub modified: bool,
+    /// Short display name of the buffer: its `*name*` when it has no
+    /// file, the file's basename otherwise.
+    pub name: String,
+    /// Full path of the backing file, for clients that want to show it
+    /// in place of the short name.
+    pub filepath: Option<std::path::PathBuf>,
+    /// Line-ending style of the loaded contents, `"LF"` or `"CRLF"`,
+    /// for the status line. Never converted, only reported.
+    pub line_ending: String,
+    /// Encoding label for the status line: `"UTF-8"`, or `"UTF-8 BOM"`
+    /// when the file carried a byte-order mark.
+    pub encoding: String,
+    /// 1-based position of this pane's buffer among all open buffers,
+    /// shown as `2/5` in the status line.
+    pub buffer_index: usize,
+    pub total_buffers: usize,
+    /// How many open buffers have unsaved changes, so the status line
+    /// can flag dirty buffers that aren't on screen.
+    pub modified_buffers: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Message {
+    /// Client -> server: a new client has connected and wants the initial
+    /// state.
+    ClientStart,
+    /// Client -> server: the user pressed a key.
+    KeyPress(Key),
+    /// Client -> server: several keys pressed in quick succession, e.g.
+    /// a held-down movement key. The server applies them in order and
+    /// pushes a single state update.
+    KeyBatch { keys: Vec<Key> },
+    /// Client -> server: a bracketed paste. The whole block is inserted
+    /// in one operation instead of being replayed as keystrokes.
+    Paste { text: String },
+    /// Client -> server: open the file at `path` into a buffer, reusing
+    /// an existing buffer for the same file. `line` optionally places the
+    /// cursor on a zero-indexed line.
+    Open {
+        path: std::path::PathBuf,
+        line: Option<usize>,
+    },
+    /// Client -> server: open a buffer with no backing file, e.g. piped
+    /// stdin read by the client.
+    OpenScratch { name: String, contents: String },
+    /// Client -> server: list the files under the server's working
+    /// directory for the quick-open picker. Answered with `FileList`.
+    ListFiles,
+    /// Client -> server: run `command` through the shell with the active
+    /// selection (or the whole buffer) as stdin and replace it with the
+    /// command's stdout. A failing command leaves the buffer untouched.
+    ShellPipe { command: String },
+    /// Client -> server: the user clicked in the editor area. `line` and
+    /// `column` are buffer coordinates, already adjusted for the gutter
+    /// and scroll offset.
+    MouseClick { line: usize, column: usize },
+    /// Client -> server: the user dragged with the button held, extending
+    /// the selection from the last click.
+    MouseDrag { line: usize, column: usize },
+    /// Client -> server: the drag ended.
+    MouseUp,
+    /// Client -> server: scroll the view by `delta` lines (negative is
+    /// up) without moving the cursor.
+    Scroll { delta: i32 },
+    /// Client -> server: the client's text area is now `width` columns by
+    /// `height` rows.
+    Resize { width: usize, height: usize },
+    /// Client -> server: is anyone listening on this socket?
+    ServerStatusCheck,
+    /// Server -> client: reply to `ServerStatusCheck`.
+    ServerStatusOk,
+    /// Server -> client: redraw from this state, one entry per visible
+    /// window, left to right.
+    State(Vec<RenderData>),
+    /// Server -> client: reply to `ListFiles`, paths relative to the
+    /// server's working directory. Bounded by the walk limits in
+    /// [`crate::finder`].
+    FileList(Vec<std::path::PathBuf>),
+    /// Server -> client: show an informational message.
+    Info(String),
+    /// Server -> client: show an error message.
+    Error(String),
+    /// Server -> client: a command had no effect, e.g. a cursor move at
+    /// the buffer boundary. The frontend gives some minimal feedback,
+    /// like ringing the terminal bell.
+    Bell,
+    /// Server -> client: the server is going away; disconnect.
+    Shutdown,
+}
+
+/// Upper bound on one framed message. Anything larger is treated as a
+/// corrupt or hostile peer and the connection is dropped, rather than
+/// trusting the length prefix with an allocation.
+pub const MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+/// Checks a decoded length prefix before it is used to allocate.
+fn check_length(len: usize) -> io::Result<usize> {
+    if len == 0 || len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message length {} outside 1..={}", len, MAX_MESSAGE_SIZE),
+        ));
+    }
+
+    Ok(len)
+}
+
+/// Writes one framed message: the JSON payload preceded by its length as
+/// a big-endian u32.
+pub async fn write_message<W>(stream: &mut W, message: &Message) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let payload = encode(message)?;
+
+    stream.write_all(&(payload.len() as u32).to_be_bytes()).await?;
+    stream.write_all(&payload).await?;
+
+    Ok(())
+}
+
+/// Reads one framed message. EOF before a complete frame surfaces as
+/// `UnexpectedEof`.
+pub async fn read_message<R>(stream: &mut R) -> io::Result<Message>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len_buf = [0u8; 4];
+    stream.read_exact(&mut len_buf).await?;
+
+    let mut payload = vec![0u8; check_length(u32::from_be_bytes(len_buf) as usize)?];
+    stream.read_exact(&mut payload).await?;
+
+    decode(&payload)
+}
+
+/// Blocking counterpart of [`write_message`] for clients that don't run
+/// inside a tokio runtime.
+pub fn write_message_blocking<W: io::Write>(stream: &mut W, message: &Message) -> io::Result<()> {
+    let payload = encode(message)?;
+
+    stream.write_all(&(payload.len() as u32).to_be_bytes())?;
+    stream.write_all(&payload)?;
+
+    Ok(())
+}
+
+/// Blocking counterpart of [`read_message`].
+pub fn read_message_blocking<R: io::Read>(stream: &mut R) -> io::Result<Message> {
+    let mut len_buf = [0u8; 4];
+    stream.read_exact(&mut len_buf)?;
+
+    let mut payload = vec![0u8; check_length(u32::from_be_bytes(len_buf) as usize)?];
+    stream.read_exact(&mut payload)?;
+
+    decode(&payload)
+}
+
+fn encode(message: &Message) -> io::Result<Vec<u8>> {
+    serde_json::to_vec(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn decode(payload: &[u8]) -> io::Result<Message> {
+    serde_json::from_slice(payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn messages_round_trip_through_a_duplex_pipe() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let sent = Message::KeyPress(Key::ctrl('s'));
+
+        write_message(&mut client, &sent).await.unwrap();
+        let received = read_message(&mut server).await.unwrap();
+
+        assert_eq!(received, sent);
+    }
+
+    #[test]
+    fn oversized_and_empty_length_prefixes_are_rejected() {
+        // A hostile 4GB length must fail before the allocation, not OOM.
+        let oversized = 0xFFFF_FFFFu32.to_be_bytes();
+        let err = read_message_blocking(&mut io::Cursor::new(oversized)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Zero-length frames never reach the JSON decoder.
+        let empty = 0u32.to_be_bytes();
+        let err = read_message_blocking(&mut io::Cursor::new(empty)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn blocking_helpers_speak_the_same_framing() {
+        let mut wire = Vec::new();
+        let sent = Message::Info("hello".to_string());
+
+        write_message_blocking(&mut wire, &sent).unwrap();
+        let received = read_message_blocking(&mut io::Cursor::new(wire)).unwrap();
+
+        assert_eq!(received, sent);
+    }
+}
@@ -0,0 +1,124 @@
+//! Shell-style history for prompt inputs: Up/Down cycle previously
+//! submitted entries, and returning to the bottom restores whatever was
+//! being typed. The shell-pipe minibuffer uses it; it's kept separate so
+//! it can be tested on its own and shared by any other prompt that grows
+//! history later.
+
+/// Most entries a [`PromptHistory`] keeps before dropping the oldest.
+const HISTORY_CAP: usize = 100;
+
+/// Previously submitted prompt inputs plus the browsing cursor into
+/// them. Entries sit oldest first; browsing starts past the end, at the
+/// in-progress text.
+#[derive(Default)]
+pub struct PromptHistory {
+    entries: Vec<String>,
+    /// Where browsing currently points; `None` means the bottom, i.e.
+    /// the in-progress text rather than a history entry.
+    position: Option<usize>,
+    /// The in-progress text stashed when browsing moved off the bottom,
+    /// handed back when it returns there.
+    stashed: String,
+}
+
+impl PromptHistory {
+    /// Records a submitted entry and resets browsing to the bottom.
+    /// Consecutive duplicates collapse into one entry, and the oldest
+    /// entry is dropped past [`HISTORY_CAP`].
+    pub fn submit(&mut self, entry: String) {
+        self.position = None;
+        self.stashed.clear();
+
+        if self.entries.last() == Some(&entry) {
+            return;
+        }
+
+        self.entries.push(entry);
+        if self.entries.len() > HISTORY_CAP {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Steps up into older history, stashing `current` when leaving the
+    /// bottom. Returns the entry to show, or `None` when already at the
+    /// oldest (or the history is empty), meaning the prompt shouldn't
+    /// change.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.stashed = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(position) => position - 1,
+        };
+
+        self.position = Some(position);
+        Some(&self.entries[position])
+    }
+
+    /// Steps back down toward newer history. Returns the entry to show,
+    /// the stashed in-progress text when arriving back at the bottom,
+    /// or `None` when already there.
+    pub fn newer(&mut self) -> Option<&str> {
+        let position = self.position?;
+
+        if position + 1 < self.entries.len() {
+            self.position = Some(position + 1);
+            Some(&self.entries[position + 1])
+        } else {
+            self.position = None;
+            Some(&self.stashed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn up_retrieves_submitted_entries_in_reverse_order() {
+        let mut history = PromptHistory::default();
+        history.submit("first".to_string());
+        history.submit("second".to_string());
+        history.submit("third".to_string());
+
+        assert_eq!(history.older(""), Some("third"));
+        assert_eq!(history.older(""), Some("second"));
+        assert_eq!(history.older(""), Some("first"));
+        // At the oldest entry Up makes no further progress.
+        assert_eq!(history.older(""), None);
+    }
+
+    #[test]
+    fn down_returns_to_the_stashed_in_progress_text() {
+        let mut history = PromptHistory::default();
+        history.submit("older".to_string());
+        history.submit("newer".to_string());
+
+        assert_eq!(history.older("half-ty"), Some("newer"));
+        assert_eq!(history.older("half-ty"), Some("older"));
+        assert_eq!(history.newer(), Some("newer"));
+        assert_eq!(history.newer(), Some("half-ty"));
+        // Already at the bottom; nothing newer to show.
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn consecutive_duplicates_collapse_and_the_cap_holds() {
+        let mut history = PromptHistory::default();
+        history.submit("same".to_string());
+        history.submit("same".to_string());
+
+        assert_eq!(history.older(""), Some("same"));
+        assert_eq!(history.older(""), None);
+
+        for i in 0..HISTORY_CAP + 10 {
+            history.submit(format!("entry-{}", i));
+        }
+        assert_eq!(history.entries.len(), HISTORY_CAP);
+        assert_eq!(history.entries.first().map(String::as_str), Some("entry-10"));
+    }
+}
@@ -0,0 +1,1625 @@
+//! The terminal frontend: connects to a running iota server over its unix
+//! socket, translates crossterm events into protocol messages, and draws
+//! whatever state the server pushes back.
+
+pub mod history;
+pub mod theme;
+
+use std::borrow::Cow;
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, Event, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use crossterm::{execute, terminal};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Position, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Clear, Paragraph};
+use ratatui::{Frame, Terminal};
+
+// Aliased: ratatui has its own (pixel-space) `Position`.
+use iota_core::Position as BufferPosition;
+use iota_server::finder;
+use iota_server::protocol::{self, HighlightColor, Key, KeyCode, Message, RenderData};
+
+use crate::history::PromptHistory;
+use crate::theme::Theme;
+
+/// Default cap on how long the loop sleeps while a transient message is
+/// on screen, so the message can expire on time. With nothing on screen
+/// the loop blocks until woken instead, costing no CPU while idle.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The message-expiry poll interval, taken from the `IOTA_POLL_MS` env
+/// var. Unset, zero, or unparsable means [`DEFAULT_POLL_INTERVAL`].
+fn poll_interval() -> Duration {
+    std::env::var("IOTA_POLL_MS")
+        .ok()
+        .and_then(|ms| ms.parse::<u64>().ok())
+        .filter(|&ms| ms > 0)
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_POLL_INTERVAL)
+}
+
+/// One wakeup for the event loop. Both the socket reader and the
+/// terminal input reader feed the same channel, so the loop blocks on a
+/// single source and wakes the moment either has something.
+enum Wakeup {
+    /// A message pushed by the server.
+    Server(Message),
+    /// A terminal input event.
+    Input(Event),
+    /// The socket reader saw the connection close.
+    Disconnected,
+}
+
+/// Lines scrolled per mouse wheel notch.
+const SCROLL_LINES: i32 = 3;
+
+/// Display columns a tab expands to when whitespace is shown.
+const TAB_WIDTH: usize = 4;
+
+/// How long an informational message stays on the bottom line.
+const INFO_MESSAGE_TTL: Duration = Duration::from_secs(4);
+
+/// Errors hang around longer, since acting on them may take a moment.
+const ERROR_MESSAGE_TTL: Duration = Duration::from_secs(10);
+
+/// The client's current text area size, adjusted for the gutter and the
+/// status and echo lines, as a `Resize` message for the server.
+fn resize_message(state: &TerminalState) -> Option<Message> {
+    let (columns, rows) = terminal::size().ok()?;
+    let gutter = gutter_width(state.focused(), state.line_numbers);
+
+    Some(Message::Resize {
+        width: (columns as usize).saturating_sub(gutter),
+        height: rows.saturating_sub(2) as usize,
+    })
+}
+
+/// A message for the echo line, remembering whether it was an error so
+/// it can be themed accordingly. Messages expire after a TTL instead of
+/// lingering until something replaces them.
+struct StatusMessage {
+    text: String,
+    is_error: bool,
+    shown_at: Instant,
+}
+
+impl StatusMessage {
+    fn new(text: String, is_error: bool) -> StatusMessage {
+        StatusMessage {
+            text,
+            is_error,
+            shown_at: Instant::now(),
+        }
+    }
+
+    fn expired(&self) -> bool {
+        let ttl = if self.is_error {
+            ERROR_MESSAGE_TTL
+        } else {
+            INFO_MESSAGE_TTL
+        };
+
+        self.shown_at.elapsed() >= ttl
+    }
+}
+
+/// Shown before the minibuffer's input while a shell-pipe prompt is up.
+const PROMPT_LABEL: &str = "pipe: ";
+
+/// The minibuffer prompt, while one is up. The only prompt so far runs
+/// its submitted input through `Message::ShellPipe`.
+#[derive(Default)]
+struct PromptState {
+    /// What the user has typed so far.
+    input: String,
+}
+
+/// The quick-open file picker overlay, while it's up. Candidates come
+/// from the server's `FileList` reply; filtering happens locally per
+/// keystroke with [`finder::fuzzy_match`].
+#[derive(Default)]
+struct PickerState {
+    /// What the user has typed so far.
+    query: String,
+    /// Every candidate path the server sent, unfiltered.
+    files: Vec<std::path::PathBuf>,
+    /// Index into the current match list, clamped when drawn.
+    selected: usize,
+}
+
+/// The picker's candidates filtered and ordered by match score, best
+/// first, with the path as a tiebreak so equal scores stay stable.
+fn picker_matches(picker: &PickerState) -> Vec<&std::path::PathBuf> {
+    let mut scored: Vec<(i64, &std::path::PathBuf)> = picker
+        .files
+        .iter()
+        .filter_map(|path| {
+            finder::fuzzy_match(&picker.query, &path.to_string_lossy())
+                .map(|score| (score, path))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    scored.into_iter().map(|(_, path)| path).collect()
+}
+
+/// How the gutter labels lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineNumberMode {
+    /// 1-indexed buffer line numbers.
+    Absolute,
+    /// Distance from the cursor line, 0 on the line itself.
+    Relative,
+    /// No gutter at all.
+    None,
+}
+
+impl LineNumberMode {
+    fn next(self) -> LineNumberMode {
+        match self {
+            LineNumberMode::Absolute => LineNumberMode::Relative,
+            LineNumberMode::Relative => LineNumberMode::None,
+            LineNumberMode::None => LineNumberMode::Absolute,
+        }
+    }
+}
+
+/// Everything the client remembers between frames.
+struct TerminalState {
+    /// One pane per window the server told us to show, left to right.
+    /// Never empty; exactly one pane is focused.
+    windows: Vec<RenderData>,
+    /// Last info/error message from the server, shown on the bottom line.
+    message: Option<StatusMessage>,
+    theme: Theme,
+    /// Buffer position of the last drag we sent, so repeated drag events
+    /// within the same cell don't flood the socket.
+    last_drag: Option<(usize, usize)>,
+    /// Soft-wrap long lines at the viewport width instead of scrolling
+    /// horizontally. Display-only: cursor movement stays logical.
+    wrap: bool,
+    line_numbers: LineNumberMode,
+    /// Render tabs, spaces, and trailing whitespace visibly. Off by
+    /// default.
+    show_whitespace: bool,
+    /// Show the buffer's full file path in the status line instead of
+    /// its short name.
+    show_full_path: bool,
+    /// Tint the background of the cursor's line so it stands out. On by
+    /// default; the theme controls the color.
+    highlight_cursor_line: bool,
+    /// The quick-open picker, while it's open. Keys go to it instead of
+    /// the server.
+    picker: Option<PickerState>,
+    /// The shell-pipe minibuffer, while it's open. Keys go to it instead
+    /// of the server.
+    prompt: Option<PromptState>,
+    /// Previously run shell-pipe commands, browsed with Up/Down while
+    /// the prompt is open. Survives the prompt closing.
+    pipe_history: PromptHistory,
+    dirty: bool,
+}
+
+impl TerminalState {
+    fn new() -> TerminalState {
+        TerminalState {
+            windows: vec![RenderData {
+                lines: Vec::new(),
+                changed_lines: None,
+                highlights: Vec::new(),
+                cursor: (0, 0),
+                scroll_line: 0,
+                scroll_column: 0,
+                char_count: 0,
+                content_hash: 0,
+                selection_chars: None,
+                secondary_cursors: Vec::new(),
+                focused: true,
+                overwrite: false,
+                modified: false,
+                name: String::new(),
+                filepath: None,
+                line_ending: "LF".to_string(),
+                encoding: "UTF-8".to_string(),
+                buffer_index: 1,
+                total_buffers: 1,
+                modified_buffers: 0,
+            }],
+            message: None,
+            theme: Theme::load(),
+            last_drag: None,
+            wrap: false,
+            line_numbers: LineNumberMode::Absolute,
+            show_whitespace: false,
+            show_full_path: false,
+            highlight_cursor_line: true,
+            picker: None,
+            prompt: None,
+            pipe_history: PromptHistory::default(),
+            dirty: true,
+        }
+    }
+
+    /// Applies a state update from the server. Panes carrying a row diff
+    /// have just the listed rows patched into the last full copy; the
+    /// rest replace their pane wholesale.
+    fn apply_panes(&mut self, panes: Vec<RenderData>) {
+        let merged = panes
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut pane)| {
+                if let Some(rows) = pane.changed_lines.take() {
+                    let (mut lines, mut highlights) = self
+                        .windows
+                        .get(i)
+                        .map(|prev| (prev.lines.clone(), prev.highlights.clone()))
+                        .unwrap_or_default();
+                    highlights.resize(lines.len(), Vec::new());
+
+                    for ((row, line), runs) in
+                        rows.into_iter().zip(pane.lines).zip(pane.highlights)
+                    {
+                        if row < lines.len() {
+                            lines[row] = line;
+                            highlights[row] = runs;
+                        }
+                    }
+
+                    pane.lines = lines;
+                    pane.highlights = highlights;
+                }
+
+                pane
+            })
+            .collect();
+
+        self.windows = merged;
+    }
+
+    /// The pane holding focus; mouse positions and the status-line counts
+    /// are relative to it.
+    fn focused(&self) -> &RenderData {
+        self.windows
+            .iter()
+            .find(|pane| pane.focused)
+            .unwrap_or(&self.windows[0])
+    }
+
+    /// Index of the focused pane, for locating its column origin.
+    fn focused_index(&self) -> usize {
+        self.windows
+            .iter()
+            .position(|pane| pane.focused)
+            .unwrap_or(0)
+    }
+}
+
+/// Connects to the server at `socket_path` and runs the client until the
+/// server shuts down or the connection is lost. Each `(path, line)` in
+/// `files` is opened into its own buffer, with the first one left
+/// focused; `line` is a zero-indexed cursor target.
+pub fn run(
+    socket_path: &Path,
+    files: &[(std::path::PathBuf, Option<usize>)],
+    stdin_contents: Option<String>,
+) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut stream = connect(socket_path, tx.clone())?;
+
+    if let Some(contents) = stdin_contents {
+        send_message(
+            &mut stream,
+            &Message::OpenScratch {
+                name: "*stdin*".to_string(),
+                contents,
+            },
+        )?;
+    }
+
+    for (path, line) in files {
+        send_message(
+            &mut stream,
+            &Message::Open {
+                path: path.clone(),
+                line: *line,
+            },
+        )?;
+    }
+
+    // Re-opening the first file switches back to its existing buffer, so
+    // it ends up focused.
+    if files.len() > 1 {
+        send_message(
+            &mut stream,
+            &Message::Open {
+                path: files[0].0.clone(),
+                line: None,
+            },
+        )?;
+    }
+
+    // Ask for the current state up front; without this the screen stays
+    // blank until the first keypress triggers an update.
+    send_client_start(&mut stream)?;
+
+    terminal::enable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        terminal::EnterAlternateScreen,
+        event::EnableMouseCapture,
+        event::EnableBracketedPaste
+    )?;
+    let mut term = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    // Terminal input feeds the same channel as server messages; spawned
+    // after raw mode is on so the first read already sees raw input.
+    {
+        let tx = tx.clone();
+        thread::spawn(move || read_input(tx));
+    }
+
+    let mut state = TerminalState::new();
+    let mut result = event_loop(&mut term, &mut stream, &rx, &mut state);
+
+    // One reconnect attempt before giving up: the server may have been
+    // restarted under us. The last-known state is kept, so the first
+    // redraw after reconnecting is immediate.
+    if matches!(&result, Err(err) if is_disconnect(err)) {
+        if let Ok(new_stream) = connect(socket_path, tx.clone()) {
+            stream = new_stream;
+            // The restarted server's state may not match what we kept.
+            send_client_start(&mut stream)?;
+            state.message = Some(StatusMessage::new(
+                "Reconnected to iota server".to_string(),
+                false,
+            ));
+            state.dirty = true;
+            result = event_loop(&mut term, &mut stream, &rx, &mut state);
+        }
+    }
+
+    terminal::disable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        event::DisableBracketedPaste,
+        event::DisableMouseCapture,
+        terminal::LeaveAlternateScreen
+    )?;
+
+    match result {
+        // Swap the raw io error for something a user can act on; the
+        // terminal is already restored at this point.
+        Err(err) if is_disconnect(&err) => Err(lost_connection()),
+        other => other,
+    }
+}
+
+/// Connects to the server and spawns the reader thread that feeds `tx`.
+fn connect(socket_path: &Path, tx: mpsc::Sender<Wakeup>) -> io::Result<UnixStream> {
+    let stream = UnixStream::connect(socket_path)?;
+    let reader = stream.try_clone()?;
+
+    thread::spawn(move || read_messages(reader, tx));
+
+    Ok(stream)
+}
+
+/// The error reported when the server side of the socket goes away.
+fn lost_connection() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "lost connection to iota server",
+    )
+}
+
+/// Whether an io error means the server hung up, as opposed to a local
+/// terminal problem.
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+fn event_loop(
+    term: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    stream: &mut UnixStream,
+    rx: &mpsc::Receiver<Wakeup>,
+    state: &mut TerminalState,
+) -> io::Result<()> {
+    // Tell the server how big our text area is so it can keep the cursor
+    // inside the viewport.
+    if let Some(message) = resize_message(state) {
+        send_message(stream, &message)?;
+    }
+
+    let poll_interval = poll_interval();
+
+    loop {
+        // Expired messages clear on the next wakeup, so at worst a
+        // message lives one poll interval past its TTL.
+        if state.message.as_ref().is_some_and(|m| m.expired()) {
+            state.message = None;
+            state.dirty = true;
+        }
+
+        if state.dirty {
+            draw(term, state)?;
+            state.dirty = false;
+        }
+
+        // Block until either source has something. Only while a message
+        // is on screen is the wait capped, so its expiry isn't stuck
+        // behind the next keypress; otherwise the loop sleeps outright.
+        let first = if state.message.is_some() {
+            match rx.recv_timeout(poll_interval) {
+                Ok(wakeup) => Some(wakeup),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(lost_connection()),
+            }
+        } else {
+            Some(rx.recv().map_err(|_| lost_connection())?)
+        };
+
+        if drain_wakeups(first, rx, state, stream)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Applies `first` and then everything queued behind it, without
+/// blocking. However many wakeups were waiting — fast typing, a paste,
+/// another client editing — state updates coalesce into one `dirty`
+/// flag (at most one draw per call) and consecutive key presses into
+/// one KeyBatch, so a burst costs one round-trip and one redraw instead
+/// of one per event. Returns whether the server announced a shutdown.
+fn drain_wakeups(
+    first: Option<Wakeup>,
+    rx: &mpsc::Receiver<Wakeup>,
+    state: &mut TerminalState,
+    stream: &mut UnixStream,
+) -> io::Result<bool> {
+    let mut keys = Vec::new();
+    let mut next = first;
+
+    loop {
+        let wakeup = match next.take() {
+            Some(wakeup) => wakeup,
+            None => match rx.try_recv() {
+                Ok(wakeup) => wakeup,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return Err(lost_connection()),
+            },
+        };
+
+        match wakeup {
+            Wakeup::Server(message) => {
+                if apply_server_message(message, state)? {
+                    return Ok(true);
+                }
+            }
+            Wakeup::Input(event) => {
+                if !handle_prompt_key(&event, state, stream)?
+                    && !handle_picker_key(&event, state, stream)?
+                    && !handle_display_toggle(&event, state, stream)?
+                {
+                    match process_event(event, state) {
+                        Some(Message::KeyPress(key)) => keys.push(key),
+                        Some(message) => {
+                            // Flush first so other traffic keeps its
+                            // ordering relative to the keys before it.
+                            flush_keys(stream, &mut keys)?;
+                            send_message(stream, &message)?;
+                        }
+                        None => {}
+                    }
+                }
+            }
+            Wakeup::Disconnected => return Err(lost_connection()),
+        }
+    }
+
+    flush_keys(stream, &mut keys)?;
+    Ok(false)
+}
+
+/// Applies one message the server pushed. Returns whether it announced
+/// a shutdown.
+fn apply_server_message(message: Message, state: &mut TerminalState) -> io::Result<bool> {
+    match message {
+        Message::State(windows) if !windows.is_empty() => {
+            state.apply_panes(windows);
+            state.dirty = true;
+        }
+        Message::Info(text) => {
+            state.message = Some(StatusMessage::new(text, false));
+            state.dirty = true;
+        }
+        Message::Error(text) => {
+            state.message = Some(StatusMessage::new(text, true));
+            state.dirty = true;
+        }
+        Message::Bell => ring_bell()?,
+        Message::FileList(files) => {
+            // A listing arriving after the picker was dismissed is
+            // simply dropped.
+            if let Some(picker) = state.picker.as_mut() {
+                picker.files = files;
+                state.dirty = true;
+            }
+        }
+        Message::Shutdown => return Ok(true),
+        _ => {}
+    }
+
+    Ok(false)
+}
+
+/// Handles the display-only toggles that never leave the client: Alt-z
+/// for soft wrap, Alt-n to cycle line-number modes, Alt-. to show
+/// whitespace, Alt-p for the full file path in the status line, Alt-l
+/// for the current-line highlight. Returns whether the event was
+/// consumed.
+fn handle_display_toggle(
+    event: &Event,
+    state: &mut TerminalState,
+    stream: &mut UnixStream,
+) -> io::Result<bool> {
+    let key = match event {
+        Event::Key(key) if key.modifiers.contains(KeyModifiers::ALT) => key,
+        _ => return Ok(false),
+    };
+
+    match key.code {
+        event::KeyCode::Char('z') => {
+            state.wrap = !state.wrap;
+            state.dirty = true;
+        }
+        event::KeyCode::Char('.') => {
+            state.show_whitespace = !state.show_whitespace;
+            state.dirty = true;
+        }
+        event::KeyCode::Char('p') => {
+            state.show_full_path = !state.show_full_path;
+            state.dirty = true;
+        }
+        event::KeyCode::Char('l') => {
+            state.highlight_cursor_line = !state.highlight_cursor_line;
+            state.dirty = true;
+        }
+        event::KeyCode::Char('n') => {
+            state.line_numbers = state.line_numbers.next();
+            state.dirty = true;
+
+            // The gutter width changed, so the text area the server
+            // sizes against did too.
+            if let Some(message) = resize_message(state) {
+                send_message(stream, &message)?;
+            }
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+/// Handles the shell-pipe minibuffer: Alt-| opens it; while it's up
+/// every key press is consumed here — typing edits the command, Up/Down
+/// browse previously run commands, Enter pipes the selection (or the
+/// whole buffer) through the command via `Message::ShellPipe`, Esc
+/// dismisses. Returns whether the event was consumed.
+fn handle_prompt_key(
+    event: &Event,
+    state: &mut TerminalState,
+    stream: &mut UnixStream,
+) -> io::Result<bool> {
+    let key = match event {
+        Event::Key(key) => key,
+        _ => return Ok(false),
+    };
+
+    if state.prompt.is_none() {
+        if key.code == event::KeyCode::Char('|') && key.modifiers.contains(KeyModifiers::ALT) {
+            state.prompt = Some(PromptState::default());
+            state.dirty = true;
+            return Ok(true);
+        }
+
+        return Ok(false);
+    }
+
+    state.dirty = true;
+
+    match key.code {
+        event::KeyCode::Esc => state.prompt = None,
+        event::KeyCode::Enter => {
+            // Enter on an empty prompt just closes it.
+            if let Some(prompt) = state.prompt.take() {
+                if !prompt.input.is_empty() {
+                    state.pipe_history.submit(prompt.input.clone());
+                    send_message(
+                        stream,
+                        &Message::ShellPipe {
+                            command: prompt.input,
+                        },
+                    )?;
+                }
+            }
+        }
+        event::KeyCode::Backspace => {
+            if let Some(prompt) = state.prompt.as_mut() {
+                prompt.input.pop();
+            }
+        }
+        event::KeyCode::Up => {
+            if let Some(prompt) = state.prompt.as_mut() {
+                if let Some(entry) = state.pipe_history.older(&prompt.input) {
+                    prompt.input = entry.to_string();
+                }
+            }
+        }
+        event::KeyCode::Down => {
+            if let Some(prompt) = state.prompt.as_mut() {
+                if let Some(entry) = state.pipe_history.newer() {
+                    prompt.input = entry.to_string();
+                }
+            }
+        }
+        event::KeyCode::Char(c)
+            if !key
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            if let Some(prompt) = state.prompt.as_mut() {
+                prompt.input.push(c);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(true)
+}
+
+/// Handles the quick-open picker: Ctrl-p opens it and asks the server
+/// for the file list; while it's up every key press is consumed here —
+/// typing narrows the matches, Up/Down move the selection, Enter opens
+/// the selected file, Esc dismisses. Returns whether the event was
+/// consumed.
+fn handle_picker_key(
+    event: &Event,
+    state: &mut TerminalState,
+    stream: &mut UnixStream,
+) -> io::Result<bool> {
+    let key = match event {
+        Event::Key(key) => key,
+        _ => return Ok(false),
+    };
+
+    if state.picker.is_none() {
+        if key.code == event::KeyCode::Char('p') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            state.picker = Some(PickerState::default());
+            state.dirty = true;
+            send_message(stream, &Message::ListFiles)?;
+            return Ok(true);
+        }
+
+        return Ok(false);
+    }
+
+    state.dirty = true;
+
+    match key.code {
+        event::KeyCode::Esc => state.picker = None,
+        event::KeyCode::Enter => {
+            let selection = state.picker.as_ref().and_then(|picker| {
+                picker_matches(picker)
+                    .get(picker.selected)
+                    .map(|&path| path.clone())
+            });
+
+            // Enter with no match leaves the picker up to keep editing
+            // the query.
+            if let Some(path) = selection {
+                state.picker = None;
+                send_message(stream, &Message::Open { path, line: None })?;
+            }
+        }
+        event::KeyCode::Backspace => {
+            if let Some(picker) = state.picker.as_mut() {
+                picker.query.pop();
+                picker.selected = 0;
+            }
+        }
+        event::KeyCode::Up => {
+            if let Some(picker) = state.picker.as_mut() {
+                picker.selected = picker.selected.saturating_sub(1);
+            }
+        }
+        event::KeyCode::Down => {
+            if let Some(picker) = state.picker.as_mut() {
+                let count = picker_matches(picker).len();
+                picker.selected = (picker.selected + 1).min(count.saturating_sub(1));
+            }
+        }
+        event::KeyCode::Char(c)
+            if !key
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            if let Some(picker) = state.picker.as_mut() {
+                picker.query.push(c);
+                picker.selected = 0;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(true)
+}
+
+/// Sends collected key presses: a lone key as a plain KeyPress, more as
+/// one KeyBatch.
+fn flush_keys(stream: &mut UnixStream, keys: &mut Vec<Key>) -> io::Result<()> {
+    match keys.len() {
+        0 => Ok(()),
+        1 => send_message(stream, &Message::KeyPress(keys.remove(0))),
+        _ => send_message(
+            stream,
+            &Message::KeyBatch {
+                keys: std::mem::take(keys),
+            },
+        ),
+    }
+}
+
+/// Translates a terminal event into a protocol message, if it maps to one.
+fn process_event(event: Event, state: &mut TerminalState) -> Option<Message> {
+    match event {
+        Event::Key(key) => translate_key(key).map(Message::KeyPress),
+        Event::Paste(text) => Some(Message::Paste { text }),
+        Event::Mouse(mouse) => translate_mouse(mouse, state),
+        Event::Resize(..) => resize_message(state),
+        _ => None,
+    }
+}
+
+/// Maps a mouse event at terminal coordinates to buffer coordinates,
+/// accounting for the gutter and scroll offset. Clicks outside the editor
+/// area (the status and echo lines) are ignored; drags are clamped into it so a
+/// drag that leaves the window keeps selecting the edge line.
+fn translate_mouse(mouse: MouseEvent, state: &mut TerminalState) -> Option<Message> {
+    let (columns, rows) = terminal::size().ok()?;
+    let editor_height = rows.saturating_sub(2);
+
+    let pane = state.focused();
+    let scroll_line = pane.scroll_line;
+    let scroll_column = pane.scroll_column;
+    let gutter = gutter_width(pane, state.line_numbers);
+    // Mouse positions are relative to the focused pane's column origin.
+    let pane_origin =
+        state.focused_index() * (columns as usize / state.windows.len().max(1));
+    let position = move |row: u16, column: u16| {
+        (
+            scroll_line + row as usize,
+            (column as usize)
+                .saturating_sub(pane_origin)
+                .saturating_sub(gutter)
+                + scroll_column,
+        )
+    };
+
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            if mouse.row >= editor_height {
+                return None;
+            }
+
+            let (line, column) = position(mouse.row, mouse.column);
+            state.last_drag = Some((line, column));
+            Some(Message::MouseClick { line, column })
+        }
+        MouseEventKind::Drag(MouseButton::Left) => {
+            let row = mouse.row.min(editor_height.saturating_sub(1));
+            let (line, column) = position(row, mouse.column);
+
+            // Debounce: only forward drags that actually reach a new cell.
+            if state.last_drag == Some((line, column)) {
+                return None;
+            }
+
+            state.last_drag = Some((line, column));
+            Some(Message::MouseDrag { line, column })
+        }
+        MouseEventKind::Up(MouseButton::Left) => {
+            state.last_drag = None;
+            Some(Message::MouseUp)
+        }
+        MouseEventKind::ScrollUp => Some(Message::Scroll {
+            delta: -SCROLL_LINES,
+        }),
+        MouseEventKind::ScrollDown => Some(Message::Scroll {
+            delta: SCROLL_LINES,
+        }),
+        _ => None,
+    }
+}
+
+fn translate_key(key: KeyEvent) -> Option<Key> {
+    let code = match key.code {
+        event::KeyCode::Char(c) => KeyCode::Char(c),
+        event::KeyCode::Enter => KeyCode::Enter,
+        event::KeyCode::Backspace => KeyCode::Backspace,
+        event::KeyCode::Delete => KeyCode::Delete,
+        event::KeyCode::Esc => KeyCode::Esc,
+        event::KeyCode::Insert => KeyCode::Insert,
+        event::KeyCode::BackTab => KeyCode::BackTab,
+        event::KeyCode::Up => KeyCode::Up,
+        event::KeyCode::Down => KeyCode::Down,
+        event::KeyCode::Left => KeyCode::Left,
+        event::KeyCode::Right => KeyCode::Right,
+        _ => return None,
+    };
+
+    Some(Key {
+        code,
+        ctrl: key.modifiers.contains(KeyModifiers::CONTROL),
+        alt: key.modifiers.contains(KeyModifiers::ALT),
+    })
+}
+
+/// Width of the line-number gutter, including the space separating it
+/// from the text. Zero when numbers are off, so the text area reclaims
+/// the space.
+fn gutter_width(render_data: &RenderData, mode: LineNumberMode) -> usize {
+    if mode == LineNumberMode::None {
+        return 0;
+    }
+
+    let digits = render_data.lines.len().max(1).to_string().len();
+    digits + 1
+}
+
+/// The gutter label for buffer line `i`, without padding.
+fn line_number(mode: LineNumberMode, i: usize, cursor_line: usize) -> usize {
+    match mode {
+        LineNumberMode::Relative => i.abs_diff(cursor_line),
+        _ => i + 1,
+    }
+}
+
+/// Styles one line's text with visible whitespace: tabs become an arrow
+/// padded to [`TAB_WIDTH`], interior spaces become faint middots, and
+/// trailing spaces get a red background.
+fn whitespace_spans(text: &str) -> Vec<Span<'static>> {
+    let trailing_start = text
+        .trim_end_matches(' ')
+        .chars()
+        .count();
+
+    let faint = Style::default().add_modifier(Modifier::DIM);
+    let trailing = Style::default().bg(Color::Red);
+
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+
+    for (i, c) in text.chars().enumerate() {
+        let styled = match c {
+            '\t' => Some(Span::styled(format!("→{}", " ".repeat(TAB_WIDTH - 1)), faint)),
+            ' ' if i >= trailing_start => Some(Span::styled(" ", trailing)),
+            ' ' => Some(Span::styled("·", faint)),
+            _ => None,
+        };
+
+        match styled {
+            Some(span) => {
+                if !plain.is_empty() {
+                    spans.push(Span::raw(std::mem::take(&mut plain)));
+                }
+                spans.push(span);
+            }
+            None => plain.push(c),
+        }
+    }
+
+    if !plain.is_empty() {
+        spans.push(Span::raw(plain));
+    }
+
+    spans
+}
+
+/// The concrete style for one of the server's semantic highlight
+/// colors.
+fn highlight_style(color: HighlightColor) -> Style {
+    match color {
+        HighlightColor::Comment => Style::default().fg(Color::DarkGray),
+        HighlightColor::Number => Style::default().fg(Color::Magenta),
+    }
+}
+
+/// Styles one line's visible text from the server's highlight runs.
+/// `runs` index into the full line in chars; `scroll` is how many chars
+/// were already cut off the left edge.
+fn highlighted_spans(
+    visible: &str,
+    runs: &[(usize, usize, HighlightColor)],
+    scroll: usize,
+) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut pending = String::new();
+    let mut pending_color: Option<HighlightColor> = None;
+
+    for (i, c) in visible.chars().enumerate() {
+        let at = scroll + i;
+        let color = runs
+            .iter()
+            .find(|&&(start, end, _)| at >= start && at < end)
+            .map(|&(_, _, color)| color);
+
+        if color != pending_color && !pending.is_empty() {
+            let style = pending_color.map(highlight_style).unwrap_or_default();
+            spans.push(Span::styled(std::mem::take(&mut pending), style));
+        }
+
+        pending_color = color;
+        pending.push(c);
+    }
+
+    if !pending.is_empty() {
+        let style = pending_color.map(highlight_style).unwrap_or_default();
+        spans.push(Span::styled(pending, style));
+    }
+
+    spans
+}
+
+/// Display width of the first `column` chars of `text` when whitespace
+/// rendering expands tabs.
+fn expanded_width(text: &str, column: usize) -> usize {
+    text.chars()
+        .take(column)
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+/// The visible slice of one pane's buffer with line numbers prepended.
+/// The rows borrow from `render_data` wherever the text is shown as-is;
+/// only horizontal scrolling, whitespace rendering, and highlighting
+/// allocate, so the common frame does no per-line copies.
+fn lines_with_numbers<'a>(
+    state: &TerminalState,
+    render_data: &'a RenderData,
+    height: usize,
+) -> Vec<Line<'a>> {
+    let gutter = gutter_width(render_data, state.line_numbers);
+
+    render_data
+        .lines
+        .iter()
+        .enumerate()
+        .skip(render_data.scroll_line)
+        .take(height)
+        .map(|(i, line)| {
+            // Slice off everything left of the horizontal scroll offset.
+            // Unscrolled lines — the usual case — are borrowed whole.
+            let visible: Cow<'a, str> = if render_data.scroll_column == 0 {
+                Cow::Borrowed(line.as_str())
+            } else {
+                Cow::Owned(line.chars().skip(render_data.scroll_column).collect())
+            };
+
+            let mut spans = Vec::new();
+
+            if gutter > 0 {
+                let number = line_number(state.line_numbers, i, render_data.cursor.0);
+                spans.push(Span::styled(
+                    format!("{:>width$} ", number, width = gutter - 1),
+                    Style::default().fg(state.theme.gutter),
+                ));
+            }
+
+            // Whitespace rendering changes the text's shape (tabs
+            // expand), so it and highlighting are mutually exclusive.
+            let runs = render_data
+                .highlights
+                .get(i)
+                .map(|runs| runs.as_slice())
+                .unwrap_or(&[]);
+
+            if state.show_whitespace {
+                spans.extend(whitespace_spans(&visible));
+            } else if runs.is_empty() {
+                spans.push(Span::raw(visible));
+            } else {
+                spans.extend(highlighted_spans(&visible, runs, render_data.scroll_column));
+            }
+
+            let mut row = Line::from(spans);
+
+            // Secondary carets on this line, dimmer than the terminal's
+            // own cursor so the primary stays recognizable.
+            for &(line, column) in &render_data.secondary_cursors {
+                if line == i && column >= render_data.scroll_column {
+                    row = mark_caret(row, gutter + column - render_data.scroll_column);
+                }
+            }
+
+            // The line style paints the whole row width and sits under
+            // the span styles, so the gutter color and caret marks
+            // render on top of the tint unchanged.
+            if state.highlight_cursor_line && i == render_data.cursor.0 {
+                row.style = Style::default().bg(state.theme.current_line);
+            }
+
+            row
+        })
+        .collect()
+}
+
+/// Restyles the char cell at display column `x` as an extra caret.
+fn mark_caret(line: Line<'_>, x: usize) -> Line<'_> {
+    let caret = Style::default()
+        .add_modifier(Modifier::REVERSED)
+        .add_modifier(Modifier::DIM);
+
+    let mut spans = Vec::new();
+    let mut seen = 0;
+    let mut marked = false;
+
+    for span in line.spans {
+        let chars: Vec<char> = span.content.chars().collect();
+
+        if marked || x >= seen + chars.len() {
+            seen += chars.len();
+            spans.push(span);
+            continue;
+        }
+
+        let split = x - seen;
+        seen += chars.len();
+
+        if split > 0 {
+            spans.push(Span::styled(
+                chars[..split].iter().collect::<String>(),
+                span.style,
+            ));
+        }
+        spans.push(Span::styled(chars[split].to_string(), caret));
+        if split + 1 < chars.len() {
+            spans.push(Span::styled(
+                chars[split + 1..].iter().collect::<String>(),
+                span.style,
+            ));
+        }
+
+        marked = true;
+    }
+
+    if !marked {
+        // The caret sits past the end of the line, e.g. on an empty line.
+        let pad = x.saturating_sub(seen);
+        if pad > 0 {
+            spans.push(Span::raw(" ".repeat(pad)));
+        }
+        spans.push(Span::styled(" ", caret));
+    }
+
+    Line::from(spans)
+}
+
+/// One buffer line soft-wrapped into display rows of at most `width`
+/// chars. Always yields at least one (possibly empty) row; lines that
+/// fit are borrowed rather than copied.
+fn wrap_line(line: &str, width: usize) -> Vec<Cow<'_, str>> {
+    if width == 0 || line.chars().count() <= width {
+        return vec![Cow::Borrowed(line)];
+    }
+
+    let chars: Vec<char> = line.chars().collect();
+    chars
+        .chunks(width)
+        .map(|chunk| Cow::Owned(chunk.iter().collect()))
+        .collect()
+}
+
+/// The visible slice in soft-wrap mode: every buffer line from the scroll
+/// offset expands to one or more display rows, numbered on the first row
+/// only. Returns the rows plus the `(x, y)` of the cursor within them.
+fn wrapped_lines<'a>(
+    state: &TerminalState,
+    render_data: &'a RenderData,
+    width: usize,
+    height: usize,
+) -> (Vec<Line<'a>>, (usize, usize)) {
+    let mode = state.line_numbers;
+    let gutter = gutter_width(render_data, mode);
+    let text_width = width.saturating_sub(gutter).max(1);
+
+    let mut rows = Vec::new();
+    let mut cursor = (gutter, 0);
+    let (cursor_line, cursor_column) = render_data.cursor;
+
+    for (i, line) in render_data
+        .lines
+        .iter()
+        .enumerate()
+        .skip(render_data.scroll_line)
+    {
+        if rows.len() >= height {
+            break;
+        }
+
+        for (row_index, row) in wrap_line(line, text_width).into_iter().enumerate() {
+            if i == cursor_line && row_index == cursor_column / text_width {
+                cursor = (gutter + cursor_column % text_width, rows.len());
+            }
+
+            if gutter == 0 {
+                rows.push(Line::from(Span::raw(row)));
+            } else {
+                let number = if row_index == 0 {
+                    format!(
+                        "{:>width$} ",
+                        line_number(mode, i, cursor_line),
+                        width = gutter - 1
+                    )
+                } else {
+                    " ".repeat(gutter)
+                };
+
+                rows.push(Line::from(vec![
+                    Span::styled(number, Style::default().fg(state.theme.gutter)),
+                    Span::raw(row),
+                ]));
+            }
+        }
+    }
+
+    rows.truncate(height);
+    (rows, cursor)
+}
+
+/// The echo line's message, colored by whether it reports an error.
+fn create_message_line<'a>(message: &'a StatusMessage, theme: &Theme) -> Paragraph<'a> {
+    let color = if message.is_error {
+        theme.error
+    } else {
+        theme.info
+    };
+
+    Paragraph::new(message.text.as_str()).style(Style::default().fg(color))
+}
+
+/// Widest the status line renders a full file path before cutting it
+/// down with [`truncate_path`].
+const PATH_DISPLAY_MAX: usize = 40;
+
+/// Renders `path` in at most `max` chars, dropping leading components
+/// behind a `…` so the filename end stays visible.
+fn truncate_path(path: &std::path::Path, max: usize) -> String {
+    let full = path.display().to_string();
+    let len = full.chars().count();
+
+    if len <= max {
+        return full;
+    }
+
+    let tail: String = full
+        .chars()
+        .skip(len + 1 - max.max(1))
+        .collect();
+    format!("…{}", tail)
+}
+
+/// The status line: buffer name and counts, with the selection size
+/// shown when one is active and the whole buffer's otherwise.
+fn create_count_line<'a>(
+    render_data: &RenderData,
+    show_full_path: bool,
+    theme: &Theme,
+) -> Paragraph<'a> {
+    let counts = match render_data.selection_chars {
+        Some(selected) => format!("{} selected", selected),
+        None => format!("{} chars", render_data.char_count),
+    };
+    let mode = if render_data.overwrite { "OVR" } else { "INS" };
+
+    // A star marks unsaved changes in some buffer other than this one,
+    // which would otherwise be invisible.
+    let own_modified = render_data.modified as usize;
+    let star = if render_data.modified_buffers > own_modified {
+        "*"
+    } else {
+        ""
+    };
+
+    // The one-indexing for human eyes happens in `display`, not here.
+    let (line, column) = BufferPosition::from(render_data.cursor).display();
+
+    // The short name by default; the full path only on request, and
+    // even then cut down so it can't swallow the whole line.
+    let title = match &render_data.filepath {
+        Some(path) if show_full_path => truncate_path(path, PATH_DISPLAY_MAX),
+        _ => render_data.name.clone(),
+    };
+
+    Paragraph::new(format!(
+        "{}  {}/{}{}  {}  Ln {}, Col {}  {}  {} {}",
+        title,
+        render_data.buffer_index,
+        render_data.total_buffers,
+        star,
+        mode,
+        line,
+        column,
+        counts,
+        render_data.encoding,
+        render_data.line_ending
+    ))
+    .style(Style::default().fg(theme.info))
+}
+
+fn draw(
+    term: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    state: &TerminalState,
+) -> io::Result<()> {
+    term.draw(|frame| {
+        let area = frame.area();
+        // Two reserved rows at the bottom: the status line, and under it
+        // the echo line that doubles as the minibuffer while prompting.
+        let editor_height = area.height.saturating_sub(2);
+        let status_area = Rect {
+            y: area.height.saturating_sub(2),
+            height: 1,
+            ..area
+        };
+        let echo_area = Rect {
+            y: area.height.saturating_sub(1),
+            height: 1,
+            ..area
+        };
+
+        let pane_count = state.windows.len().max(1) as u16;
+        let pane_width = area.width / pane_count;
+        let mut cursor = (0, 0);
+
+        for (i, render_data) in state.windows.iter().enumerate() {
+            let pane_area = Rect {
+                x: area.x + i as u16 * pane_width,
+                y: area.y,
+                width: pane_width,
+                height: editor_height,
+            };
+
+            let (cursor_line, cursor_column) = render_data.cursor;
+
+            let (lines, (x, y)) = if state.wrap {
+                wrapped_lines(
+                    state,
+                    render_data,
+                    pane_area.width as usize,
+                    pane_area.height as usize,
+                )
+            } else {
+                let visible_column = cursor_column.saturating_sub(render_data.scroll_column);
+
+                // With whitespace shown, tabs occupy TAB_WIDTH cells, so
+                // the cursor has to skip the expanded width instead of
+                // one cell per char.
+                let offset = if state.show_whitespace {
+                    render_data
+                        .lines
+                        .get(cursor_line)
+                        .map(|line| {
+                            let visible: String =
+                                line.chars().skip(render_data.scroll_column).collect();
+                            expanded_width(&visible, visible_column)
+                        })
+                        .unwrap_or(visible_column)
+                } else {
+                    visible_column
+                };
+
+                let x = gutter_width(render_data, state.line_numbers) + offset;
+                let y = cursor_line.saturating_sub(render_data.scroll_line);
+                (
+                    lines_with_numbers(state, render_data, pane_area.height as usize),
+                    (x, y),
+                )
+            };
+
+            // The focused pane draws at full intensity and owns the
+            // terminal cursor; the rest are dimmed.
+            let mut paragraph = Paragraph::new(lines);
+            if !render_data.focused {
+                paragraph = paragraph.style(Style::default().add_modifier(Modifier::DIM));
+            } else {
+                cursor = (pane_area.x as usize + x, y);
+            }
+
+            frame.render_widget(paragraph, pane_area);
+        }
+
+        frame.render_widget(
+            create_count_line(state.focused(), state.show_full_path, &state.theme),
+            status_area,
+        );
+
+        // The echo line: the minibuffer while a prompt is up (which also
+        // takes the cursor), a message otherwise, blank failing both.
+        if let Some(prompt) = &state.prompt {
+            frame.render_widget(
+                Paragraph::new(format!("{}{}", PROMPT_LABEL, prompt.input))
+                    .style(Style::default().fg(state.theme.info)),
+                echo_area,
+            );
+            cursor = (
+                echo_area.x as usize
+                    + PROMPT_LABEL.chars().count()
+                    + prompt.input.chars().count(),
+                echo_area.y as usize,
+            );
+        } else if let Some(message) = &state.message {
+            frame.render_widget(create_message_line(message, &state.theme), echo_area);
+        }
+
+        // The picker draws over everything and takes the cursor while
+        // it's up.
+        if let Some(picker) = &state.picker {
+            cursor = draw_picker(frame, area, picker, &state.theme);
+        }
+
+        frame.set_cursor_position(Position::new(cursor.0 as u16, cursor.1 as u16));
+    })?;
+
+    Ok(())
+}
+
+/// Draws the quick-open overlay: the query on top, the best matches
+/// below it with the selected one reversed. Returns where the terminal
+/// cursor should sit — at the end of the query.
+fn draw_picker(frame: &mut Frame, area: Rect, picker: &PickerState, theme: &Theme) -> (usize, usize) {
+    let width = area.width.min(60);
+    let height = area.height.min(14);
+    let popup = Rect {
+        x: area.width.saturating_sub(width) / 2,
+        y: area.height.saturating_sub(height) / 3,
+        width,
+        height,
+    };
+
+    let matches = picker_matches(picker);
+    let visible = height.saturating_sub(1) as usize;
+    let selected = picker.selected.min(matches.len().saturating_sub(1));
+    // Keep the selection on screen once it walks past the window.
+    let offset = selected.saturating_sub(visible.saturating_sub(1));
+
+    let mut rows = vec![Line::from(format!("> {}", picker.query))];
+    for (i, path) in matches.iter().enumerate().skip(offset).take(visible) {
+        let text = truncate_path(path, width.saturating_sub(1) as usize);
+        let style = if i == selected {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+
+        rows.push(Line::from(Span::styled(text, style)));
+    }
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(rows).style(Style::default().fg(theme.info)),
+        popup,
+    );
+
+    (
+        popup.x as usize + 2 + picker.query.chars().count(),
+        popup.y as usize,
+    )
+}
+
+/// Reads framed messages off the socket and forwards them to the event
+/// loop. The loop's channel has other senders, so a closed connection is
+/// announced with an explicit [`Wakeup::Disconnected`] rather than by
+/// dropping the sender.
+fn read_messages(mut stream: UnixStream, tx: mpsc::Sender<Wakeup>) {
+    loop {
+        let message = match protocol::read_message_blocking(&mut stream) {
+            Ok(message) => message,
+            Err(_) => {
+                let _ = tx.send(Wakeup::Disconnected);
+                return;
+            }
+        };
+
+        if tx.send(Wakeup::Server(message)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads terminal input events and forwards them to the event loop,
+/// blocking in [`event::read`] so an idle client costs no CPU. Exits
+/// when the loop goes away.
+fn read_input(tx: mpsc::Sender<Wakeup>) {
+    loop {
+        let event = match event::read() {
+            Ok(event) => event,
+            Err(_) => return,
+        };
+
+        if tx.send(Wakeup::Input(event)).is_err() {
+            return;
+        }
+    }
+}
+
+fn send_message(stream: &mut UnixStream, message: &Message) -> io::Result<()> {
+    protocol::write_message_blocking(stream, message)
+}
+
+/// Requests the server's current state, which comes back as a `State`
+/// update through the reader channel like any other.
+fn send_client_start(stream: &mut UnixStream) -> io::Result<()> {
+    send_message(stream, &Message::ClientStart)
+}
+
+/// Rings the terminal bell, leaving it to the emulator whether that
+/// means a sound or a visual flash.
+fn ring_bell() -> io::Result<()> {
+    use std::io::Write;
+
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x07")?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_picker_filters_and_ranks_its_candidates() {
+        use std::path::PathBuf;
+
+        let picker = PickerState {
+            query: "ed".to_string(),
+            files: vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("src/editor.rs"),
+                PathBuf::from("Cargo.toml"),
+            ],
+            selected: 0,
+        };
+
+        let matches = picker_matches(&picker);
+        assert_eq!(
+            matches,
+            vec![&PathBuf::from("src/editor.rs"), &PathBuf::from("README.md")]
+        );
+    }
+
+    #[test]
+    fn queued_updates_coalesce_into_a_single_redraw() {
+        let (mut stream, mut server_end) = UnixStream::pair().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let mut state = TerminalState::new();
+        state.dirty = false;
+
+        for i in 0..5 {
+            let mut pane = state.windows[0].clone();
+            pane.lines = vec![format!("update {}", i)];
+            tx.send(Wakeup::Server(Message::State(vec![pane]))).unwrap();
+        }
+        tx.send(Wakeup::Server(Message::Info("done".to_string())))
+            .unwrap();
+        // Interleaved key presses batch rather than going out one each.
+        for c in "hi!".chars() {
+            let key = KeyEvent::new(event::KeyCode::Char(c), KeyModifiers::NONE);
+            tx.send(Wakeup::Input(Event::Key(key))).unwrap();
+        }
+
+        assert!(!drain_wakeups(None, &rx, &mut state, &mut stream).unwrap());
+        // One call consumed the whole backlog; the screen only went
+        // dirty (one draw's worth), ending on the newest state.
+        assert!(state.dirty);
+        assert_eq!(state.windows[0].lines, vec!["update 4".to_string()]);
+        assert!(matches!(rx.try_recv(), Err(mpsc::TryRecvError::Empty)));
+
+        let sent = protocol::read_message_blocking(&mut server_end).unwrap();
+        assert!(matches!(sent, Message::KeyBatch { keys } if keys.len() == 3));
+
+        tx.send(Wakeup::Server(Message::Shutdown)).unwrap();
+        assert!(drain_wakeups(None, &rx, &mut state, &mut stream).unwrap());
+    }
+
+    #[test]
+    fn the_minibuffer_collects_a_command_and_pipes_it_on_enter() {
+        let (mut stream, mut server_end) = UnixStream::pair().unwrap();
+        let mut state = TerminalState::new();
+
+        let alt = |c| Event::Key(KeyEvent::new(event::KeyCode::Char(c), KeyModifiers::ALT));
+        let plain = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+
+        assert!(handle_prompt_key(&alt('|'), &mut state, &mut stream).unwrap());
+        for c in "sort".chars() {
+            let event = plain(event::KeyCode::Char(c));
+            assert!(handle_prompt_key(&event, &mut state, &mut stream).unwrap());
+        }
+        assert!(handle_prompt_key(&plain(event::KeyCode::Enter), &mut state, &mut stream).unwrap());
+        assert!(state.prompt.is_none());
+
+        let sent = protocol::read_message_blocking(&mut server_end).unwrap();
+        assert!(matches!(sent, Message::ShellPipe { command } if command == "sort"));
+
+        // Reopening the prompt and pressing Up recalls the command.
+        handle_prompt_key(&alt('|'), &mut state, &mut stream).unwrap();
+        handle_prompt_key(&plain(event::KeyCode::Up), &mut state, &mut stream).unwrap();
+        assert_eq!(state.prompt.as_ref().unwrap().input, "sort");
+    }
+
+    #[test]
+    fn only_the_cursor_line_is_tinted_and_the_toggle_clears_it() {
+        let mut state = TerminalState::new();
+        state.windows[0].lines = vec!["one".to_string(), "two".to_string()];
+        state.windows[0].cursor = (1, 0);
+
+        let rows = lines_with_numbers(&state, &state.windows[0], 10);
+        assert_eq!(rows[0].style.bg, None);
+        assert_eq!(rows[1].style.bg, Some(state.theme.current_line));
+        drop(rows);
+
+        state.highlight_cursor_line = false;
+        let rows = lines_with_numbers(&state, &state.windows[0], 10);
+        assert_eq!(rows[1].style.bg, None);
+    }
+
+    #[test]
+    fn the_reader_announces_a_hangup_and_exits() {
+        let (reader, writer) = UnixStream::pair().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || read_messages(reader, tx));
+
+        // The server going away closes our end of the socket; the reader
+        // thread must announce it on the shared channel and exit rather
+        // than spin or panic, which is what the event loop turns into a
+        // reconnect.
+        drop(writer);
+        handle.join().expect("reader thread exits cleanly");
+        assert!(matches!(rx.recv(), Ok(Wakeup::Disconnected)));
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn short_paths_are_shown_whole() {
+        let path = std::path::Path::new("/tmp/notes.txt");
+        assert_eq!(truncate_path(path, 40), "/tmp/notes.txt");
+    }
+
+    #[test]
+    fn long_paths_keep_the_filename_behind_an_ellipsis() {
+        let path = std::path::Path::new("/very/deeply/nested/directory/tree/main.rs");
+        assert_eq!(truncate_path(path, 12), "…ree/main.rs");
+    }
+
+    #[test]
+    fn disconnect_errors_are_recognized() {
+        assert!(is_disconnect(&lost_connection()));
+        assert!(is_disconnect(&io::Error::from(io::ErrorKind::BrokenPipe)));
+        assert!(!is_disconnect(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+    }
+}
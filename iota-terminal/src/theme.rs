@@ -0,0 +1,135 @@
+//! Color theme for the terminal, loaded from the user's config directory
+//! with sensible defaults when no theme file exists.
+
+use std::fs;
+
+use ratatui::style::Color;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+
+/// Colors for each part of the UI. Fields map one-to-one onto keys in
+/// `theme.toml`; missing keys fall back to the defaults.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    #[serde(deserialize_with = "deserialize_color")]
+    pub gutter: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub status_fg: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub status_bg: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub error: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub info: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub selection: Color,
+    /// Background for the line the cursor is on.
+    #[serde(deserialize_with = "deserialize_color")]
+    pub current_line: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            gutter: Color::DarkGray,
+            status_fg: Color::Black,
+            status_bg: Color::Gray,
+            error: Color::Red,
+            info: Color::Reset,
+            selection: Color::Blue,
+            current_line: Color::Rgb(0x28, 0x28, 0x28),
+        }
+    }
+}
+
+impl Theme {
+    /// Loads `theme.toml` from the config directory resolved by
+    /// [`iota_server::get_config_dir`], falling back to the default
+    /// theme if the file is missing or malformed.
+    pub fn load() -> Theme {
+        iota_server::get_config_dir()
+            .map(|dir| dir.join("theme.toml"))
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Parses the 16 standard named colors plus `#rrggbb` hex.
+fn parse_color(name: &str) -> Option<Color> {
+    let color = match name.to_ascii_lowercase().as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "reset" | "default" => Color::Reset,
+        hex => {
+            let hex = hex.strip_prefix('#')?;
+
+            if hex.len() != 6 {
+                return None;
+            }
+
+            let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+            let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+            let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+            Color::Rgb(r, g, b)
+        }
+    };
+
+    Some(color)
+}
+
+fn deserialize_color<'de, D>(deserializer: D) -> Result<Color, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+
+    parse_color(&name)
+        .ok_or_else(|| de::Error::custom(format!("unrecognized color {:?}", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserializes_a_sample_theme() {
+        let theme: Theme = toml::from_str(
+            r##"
+            gutter = "darkgray"
+            status_fg = "#1e1e2e"
+            status_bg = "cyan"
+            error = "LightRed"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(theme.gutter, Color::DarkGray);
+        assert_eq!(theme.status_fg, Color::Rgb(0x1e, 0x1e, 0x2e));
+        assert_eq!(theme.status_bg, Color::Cyan);
+        assert_eq!(theme.error, Color::LightRed);
+        // Unspecified keys keep their defaults.
+        assert_eq!(theme.info, Theme::default().info);
+    }
+
+    #[test]
+    fn bad_colors_are_rejected() {
+        assert!(toml::from_str::<Theme>(r#"gutter = "mauve-ish""#).is_err());
+        assert!(toml::from_str::<Theme>(r##"gutter = "#12345""##).is_err());
+    }
+}
@@ -1,3 +1,255 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use iota_server::Server;
+
+/// A file to open, optionally at a 1-indexed line (`file.txt:42` or
+/// `+42 file.txt`).
+#[derive(Debug, PartialEq, Eq)]
+struct FileTarget {
+    path: PathBuf,
+    line: Option<usize>,
+}
+
+/// Splits a `path:42` argument into path and line. A non-numeric suffix
+/// means the colon is part of the filename.
+fn parse_file_arg(arg: &str) -> FileTarget {
+    if let Some((path, line)) = arg.rsplit_once(':') {
+        if !path.is_empty() {
+            if let Ok(line) = line.parse::<usize>() {
+                return FileTarget {
+                    path: PathBuf::from(path),
+                    line: Some(line),
+                };
+            }
+        }
+    }
+
+    FileTarget {
+        path: PathBuf::from(arg),
+        line: None,
+    }
+}
+
+/// Parsed command-line arguments.
+struct Args {
+    /// Run the daemon instead of a client.
+    server: bool,
+    /// Reopen the files from the previous session on server startup.
+    restore: bool,
+    files: Vec<FileTarget>,
+}
+
+impl Args {
+    fn from_iter<I: IntoIterator<Item = String>>(args: I) -> Args {
+        let mut server = false;
+        let mut restore = false;
+        let mut files = Vec::new();
+        // A leading `+N` applies to the file that follows it.
+        let mut pending_line = None;
+
+        for arg in args {
+            if arg == "--server" {
+                server = true;
+            } else if arg == "--restore" {
+                restore = true;
+            } else if let Some(line) = arg
+                .strip_prefix('+')
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                pending_line = Some(line);
+            } else {
+                let mut target = parse_file_arg(&arg);
+
+                if target.line.is_none() {
+                    target.line = pending_line.take();
+                }
+
+                files.push(target);
+            }
+        }
+
+        Args {
+            server,
+            restore,
+            files,
+        }
+    }
+
+    fn parse() -> Args {
+        Args::from_iter(env::args().skip(1))
+    }
+}
+
+fn run_server(restore: bool) -> i32 {
+    let server = match Server::local() {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("could not start server: {}", err);
+            return 1;
+        }
+    };
+
+    let runtime = tokio::runtime::Runtime::new().expect("failed to build tokio runtime");
+
+    match runtime.block_on(async {
+        if restore {
+            server.restore_session().await;
+        }
+
+        server.run().await
+    }) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("server error: {}", err);
+            1
+        }
+    }
+}
+
+/// Makes sure a server is listening on `socket_path`, spawning a
+/// detached daemon if nothing answers. If another client wins the race
+/// and its daemon claims the socket first, ours refuses to start and we
+/// simply connect to the winner's.
+fn ensure_server_running(socket_path: &std::path::Path, restore: bool) -> std::io::Result<()> {
+    use std::time::{Duration, Instant};
+
+    if iota_server::probe_socket(socket_path) {
+        return Ok(());
+    }
+
+    eprintln!("info: no server on {}, starting one", socket_path.display());
+
+    let mut command = process::Command::new(env::current_exe()?);
+    command.arg("--server");
+
+    if restore {
+        command.arg("--restore");
+    }
+
+    command
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()?;
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+
+    while Instant::now() < deadline {
+        if iota_server::probe_socket(socket_path) {
+            eprintln!("info: server is up");
+            return Ok(());
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        "server did not answer within 2 seconds",
+    ))
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args = Args::parse();
+
+    if args.server {
+        process::exit(run_server(args.restore));
+    }
+
+    let socket_path = iota_server::get_socket_path();
+
+    if let Err(err) = ensure_server_running(&socket_path, args.restore) {
+        eprintln!("could not reach a server: {}", err);
+        process::exit(1);
+    }
+
+    // A lone `-` means "read stdin into a scratch buffer". Stdin has to
+    // be drained here, before the terminal UI takes over raw-mode input.
+    let mut stdin_contents = None;
+    let mut targets = args.files;
+
+    if targets.iter().any(|t| t.path == Path::new("-")) {
+        use std::io::Read;
+
+        let mut contents = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut contents) {
+            eprintln!("could not read stdin: {}", err);
+            process::exit(1);
+        }
+
+        stdin_contents = Some(contents);
+        targets.retain(|t| t.path != Path::new("-"));
+    }
+
+    // The editor is zero-indexed internally; the CLI is 1-indexed.
+    let files: Vec<(PathBuf, Option<usize>)> = targets
+        .into_iter()
+        .map(|target| (target.path, target.line.map(|n| n.saturating_sub(1))))
+        .collect();
+
+    if let Err(err) = iota_terminal::run(&socket_path, &files, stdin_contents) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Args {
+        Args::from_iter(list.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parses_a_colon_line_suffix() {
+        let args = args(&["file.txt:10"]);
+        assert_eq!(
+            args.files,
+            vec![FileTarget {
+                path: PathBuf::from("file.txt"),
+                line: Some(10),
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_a_leading_plus_line() {
+        let args = args(&["+5", "file.txt"]);
+        assert_eq!(
+            args.files,
+            vec![FileTarget {
+                path: PathBuf::from("file.txt"),
+                line: Some(5),
+            }]
+        );
+    }
+
+    #[test]
+    fn plus_line_applies_only_to_the_next_file() {
+        let args = args(&["+5", "a.txt", "b.txt"]);
+        assert_eq!(args.files[0].line, Some(5));
+        assert_eq!(args.files[1].line, None);
+    }
+
+    #[test]
+    fn restore_is_a_flag_not_a_file() {
+        let args = args(&["--restore", "file.txt"]);
+        assert!(args.restore);
+        assert_eq!(args.files.len(), 1);
+    }
+
+    #[test]
+    fn colons_in_filenames_are_preserved_when_not_numeric() {
+        let args = args(&["notes:draft.txt"]);
+        assert_eq!(
+            args.files,
+            vec![FileTarget {
+                path: PathBuf::from("notes:draft.txt"),
+                line: None,
+            }]
+        );
+    }
 }